@@ -0,0 +1,48 @@
+// cargo bench --bench differentiate [--features bench-memo]
+//
+// Times differentiate on expressions where the same subtree occurs many times, the case a
+// memo table of already computed subderivatives would target. Each line reports the input
+// and derivative sizes and the mean time per call; with the bench-memo feature it also times
+// the memo prototype, Expression::differentiate_memo, on the same input.
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use wavesurf::calculus::Differentiate;
+use wavesurf::{parse, Expression};
+
+fn mean_time(runs: u32, mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..runs {
+        f();
+    }
+    start.elapsed() / runs
+}
+
+fn main() {
+    let base = parse("sin(x)*e^x + ln(x)*cos(x)").unwrap();
+    // e_{k+1} = e_k * e_k + sin(e_k): every level repeats the previous one three times
+    let nested = (0..7).fold(base.clone(), |e, _| e.clone() * e.clone() + Expression::sin(e));
+    let wide = (0..200).fold(base.clone(), |sum, _| sum + base.clone());
+
+    for (name, expr, runs) in [("single", &base, 10_000), ("wide", &wide, 100), ("nested", &nested, 5)] {
+        let derivative = expr.differentiate("x");
+        let elapsed = mean_time(runs, || {
+            black_box(black_box(expr).differentiate("x"));
+        });
+        print!(
+            "{:8} {:>7} nodes -> {:>8} nodes  {:>12?} per call",
+            name,
+            expr.node_count(),
+            derivative.node_count(),
+            elapsed
+        );
+        #[cfg(feature = "bench-memo")]
+        {
+            assert_eq!(expr.differentiate_memo("x"), derivative);
+            let memo = mean_time(runs, || {
+                black_box(black_box(expr).differentiate_memo("x"));
+            });
+            print!("  {:>12?} memo", memo);
+        }
+        println!();
+    }
+}
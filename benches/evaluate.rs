@@ -0,0 +1,42 @@
+// cargo bench --bench evaluate
+//
+// Samples one expression at 10,000 points, once through evaluate with a fresh binding map
+// per point, once through a pre-flattened Evaluator and once through the closure from
+// compile. Each line reports the total time for the whole sweep.
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use wavesurf::parse;
+
+const POINTS: usize = 10_000;
+
+fn sweep_time(mut f: impl FnMut(f64)) -> Duration {
+    let start = Instant::now();
+    for i in 0..POINTS {
+        f(i as f64 / POINTS as f64);
+    }
+    start.elapsed()
+}
+
+fn main() {
+    let expr = parse("sin(x)^2 + x*cos(x*y) - e^(-x^2) / (1 + y^2)").unwrap();
+
+    let naive = sweep_time(|x| {
+        let bindings = HashMap::from([("x".to_string(), x), ("y".to_string(), 0.5)]);
+        black_box(black_box(&expr).evaluate(&bindings).unwrap());
+    });
+
+    let evaluator = expr.evaluator();
+    let flattened = sweep_time(|x| {
+        black_box(black_box(&evaluator).eval(&[x, 0.5]).unwrap());
+    });
+
+    let compiled = expr.compile(&["x", "y"]);
+    let closure = sweep_time(|x| {
+        black_box(compiled(black_box(&[x, 0.5])));
+    });
+
+    println!("evaluate   {:>12?} for {} points", naive, POINTS);
+    println!("evaluator  {:>12?} for {} points", flattened, POINTS);
+    println!("compile    {:>12?} for {} points", closure, POINTS);
+}
@@ -0,0 +1,22 @@
+use crate::calculus::IntegrationError;
+use crate::expression::Expression;
+use crate::parser::{parse, ParseError};
+
+// analyze 的结果：解析、化简、对 var 求导和积分的各个阶段
+#[derive(Debug)]
+pub struct AnalysisResult {
+    pub parsed: Expression,
+    pub simplified: Expression,
+    pub derivative: Expression,
+    pub integral: Result<Expression, IntegrationError>,
+}
+
+// 解析 input 后依次化简、对 var 求导、对 var 积分；求导和积分都作用于化简后的表达式，
+// 积分结果再化简一次。只有解析失败时返回错误，积分失败记录在 integral 中
+pub fn analyze(input: &str, var: &str) -> Result<AnalysisResult, ParseError> {
+    let parsed = parse(input)?;
+    let simplified = parsed.simplify();
+    let derivative = simplified.derivative(var);
+    let integral = simplified.integrate(var).map(|integral| integral.simplify());
+    Ok(AnalysisResult { parsed, simplified, derivative, integral })
+}
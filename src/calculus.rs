@@ -1,10 +1,15 @@
+mod definite;
 mod differentiate;
 mod integrate;
 mod integration_rules;
 mod integration_state;
+mod limit;
+mod partial_fractions;
+use crate::Expression;
 use std::error::Error;
 use std::fmt;
 
+pub use definite::QuadratureMethod;
 pub use differentiate::Differentiate;
 pub use integration_state::{IntegrationState, IntegrationMethod};
 
@@ -15,6 +20,8 @@ pub enum IntegrationError {
     NotImplemented,
     InvalidInput(String),
     UnsupportedOperation(String),
+    // The integrand is not finite at this point of the interval, e.g. 1/x at 0
+    Singularity { at: f64 },
 }
 
 impl fmt::Display for IntegrationError {
@@ -25,8 +32,38 @@ impl fmt::Display for IntegrationError {
             IntegrationError::NotImplemented => write!(f, "This integration method is not yet implemented"),
             IntegrationError::InvalidInput(msg) => write!(f, "Invalid input: {}", msg),
             IntegrationError::UnsupportedOperation(msg) => write!(f, "Unsupported operation: {}", msg),
+            IntegrationError::Singularity { at } => write!(f, "Integrand is not finite at {}", at),
         }
     }
 }
 
 impl Error for IntegrationError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LimitError {
+    // 0/0 or ∞/∞ was still indeterminate after the maximum number of L'Hôpital steps
+    LhopitalDepthExceeded { expr: Expression, point: f64 },
+    // The expression cannot be evaluated on either side of the point
+    Undefined { expr: Expression, point: f64 },
+    // The two sides tend to different values, e.g. 1/x at 0
+    OneSidedLimitsDiffer { expr: Expression, point: f64 },
+    // An indeterminate form other than a quotient, such as 0 * ∞ or ∞ - ∞
+    Indeterminate { expr: Expression, point: f64 },
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LimitError::LhopitalDepthExceeded { expr, point } => {
+                write!(f, "L'Hôpital's rule did not resolve the limit of {} at {}", expr, point)
+            }
+            LimitError::Undefined { expr, point } => write!(f, "{} is undefined near {}", expr, point),
+            LimitError::OneSidedLimitsDiffer { expr, point } => {
+                write!(f, "The one-sided limits of {} at {} differ", expr, point)
+            }
+            LimitError::Indeterminate { expr, point } => write!(f, "Cannot determine the limit of {} at {}", expr, point),
+        }
+    }
+}
+
+impl Error for LimitError {}
@@ -0,0 +1,153 @@
+use crate::Expression;
+use crate::evaluate::EvalError;
+use super::IntegrationError;
+use std::collections::HashMap;
+
+// 检查奇点时在区间内的采样点数
+const SINGULARITY_SAMPLES: usize = 64;
+const SIMPSON_TOLERANCE: f64 = 1e-10;
+const SIMPSON_MAX_DEPTH: usize = 20;
+
+// integrate_numeric 使用的求积公式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuadratureMethod {
+    Trapezoidal,
+    Midpoint,
+    Simpson,
+}
+
+impl Expression {
+    // 把 [a, b] 等分成 n 段，按 method 做数值积分；不尝试求原函数。
+    // Simpson 公式需要偶数段，n 为奇数时多分一段
+    pub fn integrate_numeric(&self, var: &str, a: f64, b: f64, method: QuadratureMethod, n: usize) -> Result<f64, EvalError> {
+        if n == 0 {
+            return Err(EvalError::DomainError("quadrature needs at least one subinterval".to_string()));
+        }
+        let n = match method {
+            QuadratureMethod::Simpson if n % 2 == 1 => n + 1,
+            _ => n,
+        };
+        let h = (b - a) / n as f64;
+        let f = |i: f64| self.evaluate_at(var, a + i * h);
+
+        let sum = match method {
+            QuadratureMethod::Trapezoidal => {
+                let interior = (1..n).map(|i| f(i as f64)).sum::<Result<f64, _>>()?;
+                (f(0.0)? + f(n as f64)?) / 2.0 + interior
+            }
+            QuadratureMethod::Midpoint => (0..n).map(|i| f(i as f64 + 0.5)).sum::<Result<f64, _>>()?,
+            QuadratureMethod::Simpson => {
+                let weighted = (1..n)
+                    .map(|i| Ok(if i % 2 == 1 { 4.0 } else { 2.0 } * f(i as f64)?))
+                    .sum::<Result<f64, _>>()?;
+                (f(0.0)? + f(n as f64)? + weighted) / 3.0
+            }
+        };
+        Ok(sum * h)
+    }
+
+    pub fn integrate_definite(&self, var: &str, a: f64, b: f64) -> Result<f64, IntegrationError> {
+        if a == b {
+            return Ok(0.0);
+        }
+
+        let f = |x: f64| self.evaluate_at(var, x);
+
+        // 区间内存在奇点时直接报错，避免返回 NaN
+        for i in 0..=SINGULARITY_SAMPLES {
+            let x = a + (b - a) * i as f64 / SINGULARITY_SAMPLES as f64;
+            match f(x) {
+                Ok(y) if y.is_finite() => {}
+                Err(e @ EvalError::UnboundVariable(_)) => {
+                    return Err(IntegrationError::InvalidInput(e.to_string()));
+                }
+                _ => return Err(IntegrationError::Singularity { at: x }),
+            }
+        }
+
+        // 先用牛顿-莱布尼茨公式，原函数在端点无法求值时改用数值积分
+        if let Ok(antiderivative) = self.integrate(var) {
+            let antiderivative = antiderivative.simplify();
+            if let (Ok(upper), Ok(lower)) = (antiderivative.evaluate_at(var, b), antiderivative.evaluate_at(var, a)) {
+                let value = upper - lower;
+                if value.is_finite() {
+                    return Ok(value);
+                }
+            }
+        }
+
+        adaptive_simpson(&f, a, b)
+    }
+
+    fn evaluate_at(&self, var: &str, x: f64) -> Result<f64, EvalError> {
+        let mut bindings = HashMap::new();
+        bindings.insert(var.to_string(), x);
+        self.evaluate(&bindings)
+    }
+}
+
+struct Segment {
+    a: f64,
+    b: f64,
+    fa: f64,
+    fm: f64,
+    fb: f64,
+    whole: f64,
+}
+
+fn adaptive_simpson<F>(f: &F, a: f64, b: f64) -> Result<f64, IntegrationError>
+where
+    F: Fn(f64) -> Result<f64, EvalError>,
+{
+    let eval = |x: f64| match f(x) {
+        Ok(y) if y.is_finite() => Ok(y),
+        _ => Err(IntegrationError::Singularity { at: x }),
+    };
+
+    fn step<G>(eval: &G, seg: Segment, tol: f64, depth: usize) -> Result<f64, IntegrationError>
+    where
+        G: Fn(f64) -> Result<f64, IntegrationError>,
+    {
+        let m = (seg.a + seg.b) / 2.0;
+        let (flm, frm) = (eval((seg.a + m) / 2.0)?, eval((m + seg.b) / 2.0)?);
+        let left = (m - seg.a) / 6.0 * (seg.fa + 4.0 * flm + seg.fm);
+        let right = (seg.b - m) / 6.0 * (seg.fm + 4.0 * frm + seg.fb);
+        let delta = left + right - seg.whole;
+
+        if depth == 0 || delta.abs() <= 15.0 * tol {
+            return Ok(left + right + delta / 15.0);
+        }
+        let left_seg = Segment { a: seg.a, b: m, fa: seg.fa, fm: flm, fb: seg.fm, whole: left };
+        let right_seg = Segment { a: m, b: seg.b, fa: seg.fm, fm: frm, fb: seg.fb, whole: right };
+        Ok(step(eval, left_seg, tol / 2.0, depth - 1)? + step(eval, right_seg, tol / 2.0, depth - 1)?)
+    }
+
+    let m = (a + b) / 2.0;
+    let (fa, fm, fb) = (eval(a)?, eval(m)?, eval(b)?);
+    let whole = (b - a) / 6.0 * (fa + 4.0 * fm + fb);
+    step(&eval, Segment { a, b, fa, fm, fb, whole }, SIMPSON_TOLERANCE, SIMPSON_MAX_DEPTH)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse;
+    use super::IntegrationError;
+
+    #[test]
+    fn integrates_over_an_interval() {
+        let value = parse("x^2").unwrap().integrate_definite("x", 0.0, 3.0).unwrap();
+        assert!((value - 9.0).abs() < 1e-9, "{}", value);
+        let numeric = parse("exp(-x^2)").unwrap().integrate_definite("x", 0.0, 1.0).unwrap();
+        assert!((numeric - 0.746824132812427).abs() < 1e-9, "{}", numeric);
+        assert_eq!(parse("sin(x)").unwrap().integrate_definite("x", 2.0, 2.0).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn singular_integrands_are_an_error() {
+        let err = parse("1/x").unwrap().integrate_definite("x", -1.0, 1.0).unwrap_err();
+        assert!(matches!(err, IntegrationError::Singularity { at } if at == 0.0), "{:?}", err);
+        assert_eq!(err.to_string(), "Integrand is not finite at 0");
+        let unbound = parse("x*y").unwrap().integrate_definite("x", 0.0, 1.0).unwrap_err();
+        assert!(matches!(unbound, IntegrationError::InvalidInput(_)), "{:?}", unbound);
+    }
+}
@@ -1,11 +1,96 @@
 use crate::Expression;
+use std::collections::HashMap;
 
+// Recurses over the tree like simplify, so the same limits apply: nesting (parentheses,
+// function calls, unary minus, exponents) up to about 100 levels is safe on a 2MB thread
+// stack in an unoptimised build, while left-associative chains such as a + b + c are walked
+// iteratively and do not add to the recursion. The derivative is deeper than its input, so
+// simplifying it is only safe up to about parser::DEFAULT_MAX_DEPTH levels of nesting and
+// parser::DEFAULT_MAX_CHAIN operators per chain, which every tree from parse stays within.
 pub trait Differentiate {
     fn differentiate(&self, var: &str) -> Expression;
 }
 
-impl Differentiate for Expression {
-    fn differentiate(&self, var: &str) -> Expression {
+impl Expression {
+    // differentiate followed by simplify_fully. Use Differentiate::differentiate directly
+    // when the raw, unsimplified tree is wanted (e.g. to inspect which rules fired).
+    pub fn derivative(&self, var: &str) -> Expression {
+        self.differentiate(var).simplify_fully()
+    }
+
+    // Differentiates n times, simplifying after every step to keep the tree small.
+    // differentiate_n(var, 0) returns a clone of the expression.
+    pub fn differentiate_n(&self, var: &str, n: usize) -> Expression {
+        (0..n).fold(self.clone(), |expr, _| expr.differentiate(var).simplify())
+    }
+
+    // Simplified partial derivatives in the order of `vars`.
+    pub fn gradient(&self, vars: &[&str]) -> Vec<Expression> {
+        vars.iter()
+            .map(|var| self.differentiate(var).simplify())
+            .collect()
+    }
+
+    // Simplified partial derivatives with respect to every free variable, keyed by name.
+    pub fn differentiate_all(&self) -> HashMap<String, Expression> {
+        self.variables()
+            .into_iter()
+            .map(|var| {
+                let partial = self.differentiate(&var).simplify();
+                (var, partial)
+            })
+            .collect()
+    }
+
+    // A left-associative chain such as a + b - c or x * y / z is walked down its left
+    // operands and differentiated bottom-up, so its length does not add to the recursion depth.
+    fn differentiate_chain<F>(&self, d: &mut F) -> Expression
+    where
+        F: FnMut(&Expression) -> Expression,
+    {
+        let mut chain = Vec::new();
+        let mut bottom = self;
+        while let Expression::Add(left, _)
+        | Expression::Subtract(left, _)
+        | Expression::Multiply(left, _)
+        | Expression::Divide(left, _) = bottom
+        {
+            chain.push(bottom);
+            bottom = left;
+        }
+
+        let mut derivative = d(bottom);
+        for node in chain.into_iter().rev() {
+            // derivative is d/dx of the node's left operand
+            derivative = match node {
+                Expression::Add(_, right) => Expression::add(derivative, d(right)),
+                Expression::Subtract(_, right) => Expression::subtract(derivative, d(right)),
+                Expression::Multiply(left, right) => {
+                    // Product rule: d(u*v) = u*dv + v*du
+                    let du_v = Expression::multiply(derivative, (**right).clone());
+                    let u_dv = Expression::multiply((**left).clone(), d(right));
+                    Expression::add(du_v, u_dv)
+                }
+                Expression::Divide(left, right) => {
+                    // Quotient rule: d(u/v) = (v*du - u*dv)/(v^2)
+                    let v_du = Expression::multiply((**right).clone(), derivative);
+                    let u_dv = Expression::multiply((**left).clone(), d(right));
+                    let numerator = Expression::subtract(v_du, u_dv);
+                    let denominator = Expression::power((**right).clone(), Expression::constant(2.0));
+                    Expression::divide(numerator, denominator)
+                }
+                _ => unreachable!("only arithmetic nodes form chains"),
+            };
+        }
+        derivative
+    }
+
+    // The rules themselves. Operands are differentiated through d, which is plain recursion
+    // for differentiate and a cached lookup for the benchmark-only memo prototype.
+    fn differentiate_node<F>(&self, var: &str, d: &mut F) -> Expression
+    where
+        F: FnMut(&Expression) -> Expression,
+    {
         match self {
             Expression::Constant(_) => Expression::constant(0.0),
             Expression::Variable(name) => {
@@ -15,26 +100,10 @@ impl Differentiate for Expression {
                     Expression::constant(0.0)
                 }
             }
-            Expression::Add(left, right) => {
-                Expression::add(left.differentiate(var), right.differentiate(var))
-            }
-            Expression::Subtract(left, right) => {
-                Expression::subtract(left.differentiate(var), right.differentiate(var))
-            }
-            Expression::Multiply(left, right) => {
-                // Product rule: d(u*v) = u*dv + v*du
-                let du_v = Expression::multiply(left.differentiate(var), (**right).clone());
-                let u_dv = Expression::multiply((**left).clone(), right.differentiate(var));
-                Expression::add(du_v, u_dv)
-            }
-            Expression::Divide(left, right) => {
-                // Quotient rule: d(u/v) = (v*du - u*dv)/(v^2)
-                let v_du = Expression::multiply((**right).clone(), left.differentiate(var));
-                let u_dv = Expression::multiply((**left).clone(), right.differentiate(var));
-                let numerator = Expression::subtract(v_du, u_dv);
-                let denominator = Expression::power((**right).clone(), Expression::constant(2.0));
-                Expression::divide(numerator, denominator)
+            Expression::Add(..) | Expression::Subtract(..) | Expression::Multiply(..) | Expression::Divide(..) => {
+                self.differentiate_chain(d)
             }
+            Expression::Negate(expr) => Expression::negate(d(expr)),
             Expression::Power(base, exponent) => {
                 match &**exponent {
                     Expression::Constant(n) => {
@@ -45,40 +114,65 @@ impl Differentiate for Expression {
                         );
                         Expression::multiply(
                             Expression::constant(*n),
-                            Expression::multiply(new_power, base.differentiate(var)),
+                            Expression::multiply(new_power, d(base)),
                         )
                     }
                     _ => {
-                        // General case using logarithmic differentiation
-                        let ln_base = Expression::ln((**base).clone());
-                        let derivative = Expression::multiply(
-                            (**exponent).clone(),
-                            Expression::multiply(ln_base, base.differentiate(var)),
+                        // General case using logarithmic differentiation:
+                        // d(u^v) = u^v * (v' * ln(u) + v * u' / u)
+                        let ln_term = Expression::multiply(
+                            d(exponent),
+                            Expression::ln((**base).clone()),
+                        );
+                        let base_term = Expression::divide(
+                            Expression::multiply((**exponent).clone(), d(base)),
+                            (**base).clone(),
                         );
-                        derivative
+                        Expression::multiply(
+                            self.clone(),
+                            Expression::add(ln_term, base_term),
+                        )
                     }
                 }
             }
             Expression::Root(base, n) => {
-                // Convert root to power and differentiate
-                let power = Expression::divide(Expression::constant(1.0), (**n).clone());
-                Expression::power((**base).clone(), power).differentiate(var)
+                match &**n {
+                    Expression::Constant(n) => {
+                        // d/dx u^(1/n) = u^(1/n - 1) * du/dx / n
+                        Expression::divide(
+                            Expression::multiply(
+                                Expression::power((**base).clone(), Expression::constant(1.0 / n - 1.0)),
+                                d(base),
+                            ),
+                            Expression::constant(*n),
+                        )
+                    }
+                    _ => {
+                        // Non-constant index: rewrite as u^(1/n) and use the general power rule
+                        let power = Expression::divide(Expression::constant(1.0), (**n).clone());
+                        d(&Expression::power((**base).clone(), power))
+                    }
+                }
+            }
+            Expression::Sqrt(expr) => {
+                // d/dx sqrt(u) = du/dx / (2*sqrt(u))
+                Expression::divide(
+                    d(expr),
+                    Expression::multiply(Expression::constant(2.0), self.clone())
+                )
             }
             Expression::Sin(expr) => {
                 // d/dx sin(u) = cos(u) * du/dx
                 Expression::multiply(
                     Expression::cos((**expr).clone()),
-                    expr.differentiate(var)
+                    d(expr)
                 )
             }
             Expression::Cos(expr) => {
                 // d/dx cos(u) = -sin(u) * du/dx
                 Expression::multiply(
-                    Expression::multiply(
-                        Expression::constant(-1.0),
-                        Expression::sin((**expr).clone())
-                    ),
-                    expr.differentiate(var)
+                    Expression::negate(Expression::sin((**expr).clone())),
+                    d(expr)
                 )
             }
             Expression::Tan(expr) => {
@@ -91,17 +185,46 @@ impl Differentiate for Expression {
                             Expression::constant(2.0)
                         )
                     ),
-                    expr.differentiate(var)
+                    d(expr)
+                )
+            }
+            Expression::Sec(expr) => {
+                // d/dx sec(u) = sec(u) * tan(u) * du/dx
+                Expression::multiply(
+                    Expression::multiply(
+                        Expression::sec((**expr).clone()),
+                        Expression::tan((**expr).clone())
+                    ),
+                    d(expr)
+                )
+            }
+            Expression::Csc(expr) => {
+                // d/dx csc(u) = -csc(u) * cot(u) * du/dx
+                Expression::multiply(
+                    Expression::negate(Expression::multiply(
+                        Expression::csc((**expr).clone()),
+                        Expression::cot((**expr).clone())
+                    )),
+                    d(expr)
+                )
+            }
+            Expression::Cot(expr) => {
+                // d/dx cot(u) = -csc²(u) * du/dx
+                Expression::multiply(
+                    Expression::negate(Expression::power(
+                        Expression::csc((**expr).clone()),
+                        Expression::constant(2.0)
+                    )),
+                    d(expr)
                 )
             }
             Expression::Arcsin(expr) => {
                 // d/dx arcsin(x) = 1/sqrt(1 - x^2)
                 let one = Expression::constant(1.0);
                 let two = Expression::constant(2.0);
-                let inner_deriv = (**expr).clone().differentiate(var);
-                let denom = Expression::power(
-                    Expression::subtract(one, Expression::power((**expr).clone(), two)),
-                    Expression::constant(0.5)
+                let inner_deriv = d(expr);
+                let denom = Expression::sqrt(
+                    Expression::subtract(one, Expression::power((**expr).clone(), two))
                 );
                 Expression::multiply(inner_deriv, Expression::divide(Expression::constant(1.0), denom))
             }
@@ -109,24 +232,20 @@ impl Differentiate for Expression {
                 // d/dx arccos(x) = -1/sqrt(1 - x^2)
                 let one = Expression::constant(1.0);
                 let two = Expression::constant(2.0);
-                let inner_deriv = (**expr).clone().differentiate(var);
-                let denom = Expression::power(
-                    Expression::subtract(one, Expression::power((**expr).clone(), two)),
-                    Expression::constant(0.5)
+                let inner_deriv = d(expr);
+                let denom = Expression::sqrt(
+                    Expression::subtract(one, Expression::power((**expr).clone(), two))
                 );
                 Expression::multiply(
                     inner_deriv,
-                    Expression::multiply(
-                        Expression::constant(-1.0),
-                        Expression::divide(Expression::constant(1.0), denom)
-                    )
+                    Expression::negate(Expression::divide(Expression::constant(1.0), denom))
                 )
             }
             Expression::Arctan(expr) => {
                 // d/dx arctan(x) = 1/(1 + x^2)
                 let one = Expression::constant(1.0);
                 let two = Expression::constant(2.0);
-                let inner_deriv = (**expr).clone().differentiate(var);
+                let inner_deriv = d(expr);
                 let denom = Expression::add(one, Expression::power((**expr).clone(), two));
                 Expression::multiply(inner_deriv, Expression::divide(Expression::constant(1.0), denom))
             }
@@ -134,7 +253,7 @@ impl Differentiate for Expression {
                 // d/dx e^u = e^u * du/dx
                 Expression::multiply(
                     Expression::exp((**expr).clone()),
-                    (**expr).clone().differentiate(var)
+                    d(expr)
                 )
             }
             Expression::Ln(expr) => {
@@ -144,12 +263,12 @@ impl Differentiate for Expression {
                         Expression::constant(1.0),
                         (**expr).clone()
                     ),
-                    (**expr).clone().differentiate(var)
+                    d(expr)
                 )
             }
             Expression::Log(base, expr) => {
                 // d/dx log_b(u) = 1/(u * ln(b))
-                let inner_deriv = (**expr).clone().differentiate(var);
+                let inner_deriv = d(expr);
                 let denom = Expression::multiply(
                     (**expr).clone(),
                     Expression::ln((**base).clone())
@@ -160,14 +279,14 @@ impl Differentiate for Expression {
                 // d/dx sinh(u) = cosh(u) * du/dx
                 Expression::multiply(
                     Expression::cosh((**expr).clone()),
-                    (**expr).clone().differentiate(var)
+                    d(expr)
                 )
             }
             Expression::Cosh(expr) => {
                 // d/dx cosh(u) = sinh(u) * du/dx
                 Expression::multiply(
                     Expression::sinh((**expr).clone()),
-                    (**expr).clone().differentiate(var)
+                    d(expr)
                 )
             }
             Expression::Tanh(expr) => {
@@ -180,9 +299,250 @@ impl Differentiate for Expression {
                             Expression::constant(2.0)
                         )
                     ),
-                    (**expr).clone().differentiate(var)
+                    d(expr)
+                )
+            }
+            Expression::Factorial(expr) => {
+                // d/dx u! = d/dx Γ(u + 1) = u! * ψ(u + 1) * du/dx
+                Expression::multiply(
+                    Expression::multiply(
+                        self.clone(),
+                        Expression::digamma(Expression::add((**expr).clone(), Expression::constant(1.0)))
+                    ),
+                    d(expr)
+                )
+            }
+            // Take the derivative of whichever argument is active; at a tie (u = v) this is a
+            // heuristic that picks the first argument, since min and max are not differentiable there
+            Expression::Min(left, right) => Expression::piecewise(
+                Expression::subtract((**right).clone(), (**left).clone()),
+                d(left),
+                d(right)
+            ),
+            Expression::Max(left, right) => Expression::piecewise(
+                Expression::subtract((**left).clone(), (**right).clone()),
+                d(left),
+                d(right)
+            ),
+            // Differentiate each branch; the jump where the condition changes sign is ignored
+            Expression::Piecewise(condition, if_nonnegative, otherwise) => Expression::piecewise(
+                (**condition).clone(),
+                d(if_nonnegative),
+                d(otherwise)
+            ),
+            Expression::Polygamma(order, expr) => {
+                // d/dx ψ⁽ⁿ⁾(u) = ψ⁽ⁿ⁺¹⁾(u) * du/dx; the order is treated as a constant
+                Expression::multiply(
+                    Expression::polygamma(
+                        match **order {
+                            Expression::Constant(n) => Expression::constant(n + 1.0),
+                            _ => Expression::add((**order).clone(), Expression::constant(1.0)),
+                        },
+                        (**expr).clone()
+                    ),
+                    d(expr)
+                )
+            }
+            Expression::Abs(expr) => {
+                // d/dx |u| = u/|u| * du/dx, undefined where u = 0
+                Expression::multiply(
+                    Expression::divide((**expr).clone(), self.clone()),
+                    d(expr)
+                )
+            }
+            // The step is flat on both sides of 0; the Dirac delta at the jump is dropped
+            Expression::Heaviside(_) => Expression::constant(0.0),
+            // Follow whichever of lo, hi or u is active, using the same tie rule as the evaluation
+            Expression::Clamp(expr, lo, hi) => Expression::piecewise(
+                Expression::subtract((**lo).clone(), (**expr).clone()),
+                d(lo),
+                Expression::piecewise(
+                    Expression::subtract((**expr).clone(), (**hi).clone()),
+                    d(hi),
+                    d(expr)
+                )
+            ),
+            Expression::Arctanh(expr) => {
+                // d/dx arctanh(u) = 1/(1 - u^2) * du/dx
+                let denom = Expression::subtract(
+                    Expression::constant(1.0),
+                    Expression::power((**expr).clone(), Expression::constant(2.0))
+                );
+                Expression::multiply(
+                    d(expr),
+                    Expression::divide(Expression::constant(1.0), denom)
                 )
             }
         }
     }
-}
\ No newline at end of file
+}
+
+impl Differentiate for Expression {
+    fn differentiate(&self, var: &str) -> Expression {
+        self.differentiate_node(var, &mut |operand| operand.differentiate(var))
+    }
+}
+
+// The memo table prototyped for the differentiate benchmark, kept so the benchmark can time
+// it next to the plain recursion; build with --features bench-memo. It is consistently slower:
+// children are Box, so every cache hit deep-clones a derivative as large as recomputing it
+// would build, and the numbering and hashing come on top.
+#[cfg(feature = "bench-memo")]
+mod memo {
+    use crate::Expression;
+    use std::collections::HashMap;
+    use std::mem::{discriminant, Discriminant};
+
+    // Structural identity of a node: subtrees with equal keys are equal expressions
+    #[derive(PartialEq, Eq, Hash)]
+    enum NodeKey {
+        Constant(u64),
+        Variable(String),
+        // Nodes have at most three children; missing ones are usize::MAX
+        Composite(Discriminant<Expression>, [usize; 3]),
+    }
+
+    // Derivatives already computed during one call, so repeated subtrees such as the two
+    // copies of u in u * u are differentiated once. Every node of the input is numbered up
+    // front, bottom-up, so structurally equal subtrees share a number and a lookup goes by
+    // pointer instead of hashing the subtree. Only subtrees occurring more than once are
+    // remembered; nodes built while applying the rules are differentiated without the memo.
+    pub(super) struct DerivativeMemo {
+        ids: HashMap<*const Expression, usize>,
+        derivatives: HashMap<usize, Expression>,
+    }
+
+    impl DerivativeMemo {
+        pub(super) fn new(root: &Expression) -> Self {
+            fn number(expr: &Expression, keys: &mut HashMap<NodeKey, usize>, ids: &mut HashMap<*const Expression, usize>) -> usize {
+                let key = match expr {
+                    // 0.0 == -0.0, as in the Hash impl
+                    Expression::Constant(c) => NodeKey::Constant(if *c == 0.0 { 0.0f64 } else { *c }.to_bits()),
+                    Expression::Variable(name) => NodeKey::Variable(name.clone()),
+                    _ => {
+                        let mut children = [usize::MAX; 3];
+                        for (slot, child) in children.iter_mut().zip(expr.children()) {
+                            *slot = number(child, keys, ids);
+                        }
+                        NodeKey::Composite(discriminant(expr), children)
+                    }
+                };
+                let next = keys.len();
+                let id = *keys.entry(key).or_insert(next);
+                ids.insert(expr as *const Expression, id);
+                id
+            }
+
+            let mut ids = HashMap::new();
+            let mut keys = HashMap::new();
+            number(root, &mut keys, &mut ids);
+            let mut occurrences = vec![0usize; keys.len()];
+            for id in ids.values() {
+                occurrences[*id] += 1;
+            }
+            ids.retain(|_, id| occurrences[*id] > 1);
+            DerivativeMemo { ids, derivatives: HashMap::new() }
+        }
+
+        pub(super) fn derivative(&mut self, expr: &Expression, var: &str) -> Expression {
+            let id = match expr {
+                Expression::Constant(_) | Expression::Variable(_) => None,
+                _ => self.ids.get(&(expr as *const Expression)).copied(),
+            };
+            if let Some(derivative) = id.and_then(|id| self.derivatives.get(&id)) {
+                return derivative.clone();
+            }
+            let derivative = expr.differentiate_node(var, &mut |operand| self.derivative(operand, var));
+            if let Some(id) = id {
+                self.derivatives.insert(id, derivative.clone());
+            }
+            derivative
+        }
+    }
+}
+
+#[cfg(feature = "bench-memo")]
+impl Expression {
+    // Same result as differentiate, computed through the memo prototype above
+    pub fn differentiate_memo(&self, var: &str) -> Expression {
+        memo::DerivativeMemo::new(self).derivative(self, var)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use crate::parse;
+    use std::collections::HashMap;
+
+    fn derivative(input: &str) -> String {
+        parse(input).unwrap().derivative("x").to_string()
+    }
+
+    #[test]
+    fn differentiates_reciprocal_trig_functions() {
+        assert_eq!(derivative("sec(x)"), "sec(x) * tan(x)");
+        assert_eq!(derivative("csc(x)"), "-csc(x) * cot(x)");
+        assert_eq!(derivative("cot(x)"), "-csc(x)^2");
+        assert_eq!(derivative("sec(2*x)"), "2 * sec(2 * x) * tan(2 * x)");
+    }
+
+    #[test]
+    fn differentiates_power_with_variable_exponent() {
+        assert_eq!(derivative("x^x"), "x^x * (ln(x) + 1)");
+        let bindings = HashMap::from([("x".to_string(), 1.7)]);
+        let exact = 1.7_f64.powf(1.7) * (1.7_f64.ln() + 1.0);
+        let value = parse("x^x").unwrap().derivative("x").evaluate(&bindings).unwrap();
+        assert!((value - exact).abs() < 1e-12);
+        assert_eq!(derivative("2^x"), "2^x * ln(2)");
+    }
+
+    #[test]
+    fn differentiates_roots() {
+        assert_eq!(derivative("sqrt(x)"), "1 / (2 * sqrt(x))");
+        assert_eq!(derivative("sqrt(x^2 + 1)"), "x / sqrt(x^2 + 1)");
+        let bindings = HashMap::from([("x".to_string(), 8.0)]);
+        let value = parse("cbrt(x)").unwrap().derivative("x").evaluate(&bindings).unwrap();
+        assert!((value - 1.0 / 12.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn gradient_over_discovered_variables() {
+        let expr = parse("x^2 + x*y + y^2").unwrap();
+        let names: Vec<String> = expr.variables().into_iter().collect();
+        assert_eq!(names, ["x", "y"]);
+        let vars: Vec<&str> = names.iter().map(String::as_str).collect();
+        let gradient: Vec<String> = expr.gradient(&vars).iter().map(|d| d.to_string()).collect();
+        assert_eq!(gradient, ["2 * x + y", "x + 2 * y"]);
+        assert_eq!(parse("x*sin(y)").unwrap().gradient(&["y", "z"])[1].to_string(), "0");
+    }
+
+    #[test]
+    fn differentiate_all_covers_every_free_variable() {
+        let partials = parse("x^2 + x*y").unwrap().differentiate_all();
+        assert_eq!(partials.len(), 2);
+        assert_eq!(partials["x"].to_string(), "2 * x + y");
+        assert_eq!(partials["y"].to_string(), "x");
+        assert!(parse("3").unwrap().differentiate_all().is_empty());
+    }
+
+    #[test]
+    fn differentiate_n_repeats_and_simplifies() {
+        let sin = parse("sin(x)").unwrap();
+        assert_eq!(sin.differentiate_n("x", 0), sin);
+        assert_eq!(sin.differentiate_n("x", 1).to_string(), "cos(x)");
+        assert_eq!(sin.differentiate_n("x", 2).to_string(), "-sin(x)");
+        assert_eq!(sin.differentiate_n("x", 4), sin);
+        assert_eq!(parse("x^3").unwrap().differentiate_n("x", 3).to_string(), "6");
+    }
+
+    #[cfg(feature = "bench-memo")]
+    #[test]
+    fn memo_prototype_matches_differentiate() {
+        use crate::calculus::Differentiate;
+        use crate::Expression;
+        let base = parse("sin(x)*e^x + ln(x)*cos(x)").unwrap();
+        let nested = (0..3).fold(base.clone(), |e, _| e.clone() * e.clone() + Expression::sin(e));
+        for expr in [base, nested, parse("x^x * x^x + min(x, x^2)").unwrap()] {
+            assert_eq!(expr.differentiate_memo("x"), expr.differentiate("x"));
+        }
+    }
+}
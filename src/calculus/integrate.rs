@@ -3,10 +3,11 @@ use super::IntegrationError;
 use super::integration_rules::IntegrationTable;
 use super::integration_state::{IntegrationState, IntegrationMethod};
 use super::differentiate::Differentiate;
+use super::partial_fractions::linear_factor;
+use crate::polynomial::poly_to_expression;
+use crate::simplify::scaled;
 use lazy_static::lazy_static;
 
-type IntegrationFn = Box<dyn Fn(&Expression, &str, &mut IntegrationState) -> Result<Expression, IntegrationError>>;
-
 lazy_static! {
     static ref INTEGRATION_TABLE: IntegrationTable = IntegrationTable::new();
 }
@@ -17,6 +18,18 @@ impl Expression {
         self.integrate_with_state(var, &mut state)
     }
 
+    // 不定积分加上积分常数 const_name，之后可用 substitute 代入初值条件
+    pub fn integrate_with_constant(&self, var: &str, const_name: &str) -> Result<Expression, IntegrationError> {
+        if const_name == var || self.contains_variable(const_name) {
+            return Err(IntegrationError::InvalidInput(format!(
+                "constant name {} clashes with a variable of {}",
+                const_name, self
+            )));
+        }
+        let integral = self.integrate(var)?;
+        Ok(Expression::add(integral, Expression::variable(const_name)))
+    }
+
     pub fn integrate_with_state(&self, var: &str, state: &mut IntegrationState) -> Result<Expression, IntegrationError> {
         if state.should_prune(self) {
             return Err(IntegrationError::MaxDepthExceeded);
@@ -27,28 +40,60 @@ impl Expression {
             return result;
         }
 
-        // 尝试各种积分方法
-        let methods: [(IntegrationMethod, IntegrationFn); 3] = [
-            (IntegrationMethod::Direct, Box::new(|expr, v, s| expr.try_direct_integration(v, s))),
-            (IntegrationMethod::ByParts, Box::new(|expr, v, s| expr.try_integration_by_parts(v, s))),
-            (IntegrationMethod::Substitution, Box::new(|expr, v, s| expr.try_substitution(v, s))),
+        // 按顺序尝试各种积分方法
+        let methods = [
+            IntegrationMethod::Direct,
+            IntegrationMethod::RationalFunction,
+            IntegrationMethod::ByParts,
+            IntegrationMethod::Substitution,
         ];
 
-        for (method, integration_fn) in methods.iter() {
+        let mut depth_exceeded = false;
+        for method in methods {
             let prev_method = state.get_method();
             state.set_method(method.clone());
             
-            if let Ok(result) = integration_fn(self, var, state) {
-                return Ok(result);
+            match self.integrate_by_method(&method, var, state) {
+                Ok(result) => return Ok(result),
+                Err(IntegrationError::MaxDepthExceeded) => depth_exceeded = true,
+                Err(_) => {}
             }
             
             state.set_method(prev_method);
         }
 
-        Err(IntegrationError::NoMethodFound)
+        if depth_exceeded {
+            Err(IntegrationError::MaxDepthExceeded)
+        } else {
+            Err(IntegrationError::NoMethodFound)
+        }
     }
 
-    fn try_direct_integration(&self, var: &str, _state: &mut IntegrationState) -> Result<Expression, IntegrationError> {
+    // 只用 method 处理最外层的被积函数，如 IntegrationMethod::ByParts 强制分部积分；
+    // 过程中产生的子积分仍按 integrate 的完整流程求解。Direct 包括积分表查找，
+    // TrigonometricSubstitution 尚未实现
+    pub fn integrate_using(&self, var: &str, method: IntegrationMethod) -> Result<Expression, IntegrationError> {
+        let mut state = IntegrationState::new(5);
+        if method == IntegrationMethod::Direct {
+            if let Some(result) = INTEGRATION_TABLE.lookup(self, var) {
+                return result;
+            }
+        }
+        state.set_method(method.clone());
+        self.integrate_by_method(&method, var, &mut state)
+    }
+
+    fn integrate_by_method(&self, method: &IntegrationMethod, var: &str, state: &mut IntegrationState) -> Result<Expression, IntegrationError> {
+        match method {
+            IntegrationMethod::Direct => self.try_direct_integration(var, state),
+            IntegrationMethod::RationalFunction => self.try_rational_function(var),
+            IntegrationMethod::ByParts => self.try_integration_by_parts(var, state),
+            IntegrationMethod::Substitution => self.try_substitution(var, state),
+            IntegrationMethod::TrigonometricSubstitution => Err(IntegrationError::NotImplemented),
+        }
+    }
+
+    fn try_direct_integration(&self, var: &str, state: &mut IntegrationState) -> Result<Expression, IntegrationError> {
         match self {
             Expression::Constant(c) => {
                 Ok(Expression::multiply(
@@ -72,86 +117,417 @@ impl Expression {
                     ))
                 }
             }
+            // 不含积分变量的表达式视为常数
+            _ if !self.contains_variable(var) => {
+                Ok(Expression::multiply(self.clone(), Expression::variable(var)))
+            }
             Expression::Add(left, right) => {
-                let left_int = (**left).clone().integrate_with_state(var, _state)?;
-                let right_int = (**right).clone().integrate_with_state(var, _state)?;
+                let left_int = (**left).clone().integrate_with_state(var, state)?;
+                let right_int = (**right).clone().integrate_with_state(var, state)?;
                 Ok(Expression::add(left_int, right_int))
             }
             Expression::Subtract(left, right) => {
-                let left_int = (**left).clone().integrate_with_state(var, _state)?;
-                let right_int = (**right).clone().integrate_with_state(var, _state)?;
+                let left_int = (**left).clone().integrate_with_state(var, state)?;
+                let right_int = (**right).clone().integrate_with_state(var, state)?;
                 Ok(Expression::subtract(left_int, right_int))
             }
+            Expression::Negate(expr) => Ok(Expression::negate(expr.integrate_with_state(var, state)?)),
+            // 常数因子提到积分号外
+            Expression::Multiply(left, right) if !left.contains_variable(var) => {
+                let right_int = right.integrate_with_state(var, state)?;
+                Ok(Expression::multiply((**left).clone(), right_int))
+            }
+            Expression::Multiply(left, right) if !right.contains_variable(var) => {
+                let left_int = left.integrate_with_state(var, state)?;
+                Ok(Expression::multiply(left_int, (**right).clone()))
+            }
+            Expression::Divide(left, right) if !right.contains_variable(var) => {
+                let left_int = left.integrate_with_state(var, state)?;
+                Ok(Expression::divide(left_int, (**right).clone()))
+            }
+            // 1/u^n = u^(-n)，交给幂函数的规则
+            Expression::Divide(left, right) if **left == Expression::Constant(1.0) => match &**right {
+                Expression::Power(base, exponent) if !exponent.contains_variable(var) => {
+                    let negated = Expression::negate((**exponent).clone()).simplify();
+                    Expression::power((**base).clone(), negated).integrate_with_state(var, state)
+                }
+                _ => Err(IntegrationError::NoMethodFound),
+            },
+            // 常数分子：c/f(x) = c·(1/f(x))
+            Expression::Divide(left, right) if !left.contains_variable(var) && **left != Expression::Constant(1.0) => {
+                let reciprocal = Expression::divide(Expression::constant(1.0), (**right).clone());
+                let reciprocal_int = reciprocal.integrate_with_state(var, state)?;
+                Ok(Expression::multiply((**left).clone(), reciprocal_int))
+            }
+            // ∫e^(a*x+b) = e^(a*x+b)/a
+            Expression::Exp(arg) => {
+                let a = linear_coefficient(arg, var).ok_or(IntegrationError::NoMethodFound)?;
+                Ok(divide_by_coefficient(self.clone(), a))
+            }
+            // ∫c^(a*x+b) = c^(a*x+b)/(a*ln(c))，c 不含积分变量
+            Expression::Power(base, exponent) if !base.contains_variable(var) && base.constant_value() != Some(1.0) => {
+                let a = linear_coefficient(exponent, var).ok_or(IntegrationError::NoMethodFound)?;
+                let ln_base = Expression::ln((**base).clone());
+                let integral = Expression::divide(self.clone(), ln_base);
+                Ok(divide_by_coefficient(integral, a))
+            }
+            // ∫(a*x+b)^n = (a*x+b)^(n+1)/(a*(n+1))，n = -1 时为 ln|a*x+b|/a
+            Expression::Power(base, exponent) if !exponent.contains_variable(var) => {
+                let a = linear_coefficient(base, var).ok_or(IntegrationError::NoMethodFound)?;
+                if exponent.constant_value() == Some(-1.0) {
+                    let log = Expression::ln(Expression::abs((**base).clone()));
+                    return Ok(divide_by_coefficient(log, a));
+                }
+                let raised = Expression::add((**exponent).clone(), Expression::constant(1.0)).simplify();
+                Ok(divide_by_coefficient(
+                    Expression::power((**base).clone(), raised.clone()),
+                    Expression::multiply(a, raised).simplify(),
+                ))
+            }
             _ => Err(IntegrationError::NoMethodFound),
         }
     }
 
+    // 部分分式：∫P/Q = ∫商多项式 + Σ A·ln|x - r|
+    fn try_rational_function(&self, var: &str) -> Result<Expression, IntegrationError> {
+        let decomposition = self.decompose_rational(var)?;
+        let x = Expression::variable(var);
+
+        let mut antiderivative = vec![0.0];
+        antiderivative.extend(decomposition.quotient.iter().enumerate().map(|(i, c)| c / (i as f64 + 1.0)));
+        let mut result = poly_to_expression(&antiderivative, var);
+        for (a, r) in decomposition.terms {
+            let log = Expression::ln(Expression::abs(linear_factor(&x, r)));
+            result = match result {
+                Expression::Constant(0.0) => scaled(a, log),
+                acc if a < 0.0 => Expression::subtract(acc, scaled(-a, log)),
+                acc => Expression::add(acc, scaled(a, log)),
+            };
+        }
+        Ok(result)
+    }
+
     fn try_integration_by_parts(&self, var: &str, state: &mut IntegrationState) -> Result<Expression, IntegrationError> {
+        let (u, dv) = match self {
+            Expression::Multiply(left, right) => self.choose_u_dv(left, right, var)?,
+            // ln(x)、arctan(x) 等单个函数视为 1·f(x)；ln(x)/x 这样的商不算，交给换元
+            Expression::Divide(_, _) => return Err(IntegrationError::NoMethodFound),
+            _ if self.get_liate_rank(self, var) <= 1 => (self.clone(), Expression::constant(1.0)),
+            _ => return Err(IntegrationError::NoMethodFound),
+        };
+
         state.push_expression(self.clone());
+        let result = Self::integrate_parts(&u, &dv, var, state);
+        state.pop_expression();
+        result
+    }
+
+    fn integrate_parts(u: &Expression, dv: &Expression, var: &str, state: &mut IntegrationState) -> Result<Expression, IntegrationError> {
+        // 计算du
+        let du = u.differentiate(var);
+
+        // 计算v (∫dv)
+        let v = dv.integrate_with_state(var, state)?.simplify();
+
+        // 计算∫v·du，化简时约去 v 与 du 的公因子：(x^2/2)·(1/x) = x/2
+        let v_du = Expression::multiply(v.clone(), du).simplify();
+        let v_du_int = v_du.integrate_with_state(var, state)?;
+
+        // 最终结果：u·v - ∫v·du，化简以去掉 a - (-b) 这样的双重负号
+        Ok(Expression::subtract(
+            Expression::multiply(u.clone(), v),
+            v_du_int
+        ).simplify())
+    }
+
+    fn try_substitution(&self, var: &str, state: &mut IntegrationState) -> Result<Expression, IntegrationError> {
+        state.push_expression(self.clone());
+
+        let result = self.substitution_candidates(var)
+            .iter()
+            .find_map(|u| self.integrate_by_substitution(u, var, state).ok());
+
+        state.pop_expression();
+        result.ok_or(IntegrationError::NoMethodFound)
+    }
+
+    // 令 u = g(x)：若被积函数可写成 f(u)·u'，则 ∫f(u)·u' dx = ∫f(u) du
+    fn integrate_by_substitution(&self, u: &Expression, var: &str, state: &mut IntegrationState) -> Result<Expression, IntegrationError> {
+        // 被积函数的因子都化简过，u 也要化简后才能在其中找到（如 Negate 会并入系数）
+        let u = &u.simplify();
+        let du = u.differentiate(var).simplify();
+        let (du_coeff, du_factors) = product_factors(&du);
+        if du_coeff == 0.0 {
+            return Err(IntegrationError::NoMethodFound);
+        }
+
+        // 从被积函数中约去 u' 的每个因子
+        let (coeff, mut factors) = product_factors(self);
+        for factor in du_factors {
+            match factors.iter().position(|f| *f == factor) {
+                Some(index) => {
+                    factors.remove(index);
+                }
+                None => return Err(IntegrationError::NoMethodFound),
+            }
+        }
+
+        let placeholder = Expression::variable(&fresh_variable(self));
+        let rest = factors.into_iter().fold(
+            Expression::constant(coeff / du_coeff),
+            Expression::multiply,
+        );
+        let rest = rest.replace_subexpr(u, &placeholder);
+        if rest.contains_variable(var) {
+            return Err(IntegrationError::NoMethodFound);
+        }
+
+        let Expression::Variable(u_name) = &placeholder else { unreachable!() };
+        let integral = rest.simplify().integrate_with_state(u_name, state)?;
+
+        // 回代 u = g(x)
+        Ok(integral.replace_subexpr(&placeholder, u))
+    }
+
+    // 收集可作为 u 的候选子表达式（外层优先）
+    fn substitution_candidates(&self, var: &str) -> Vec<Expression> {
+        fn collect(expr: &Expression, var: &str, candidates: &mut Vec<Expression>) {
+            for child in expr.children() {
+                let is_var = matches!(child, Expression::Variable(name) if name == var);
+                if !is_var && child.contains_variable(var) && !candidates.contains(child) {
+                    candidates.push(child.clone());
+                }
+                collect(child, var, candidates);
+            }
+        }
+
+        let mut candidates = Vec::new();
+        collect(self, var, &mut candidates);
+        candidates
+    }
+
+    fn choose_u_dv(&self, left: &Expression, right: &Expression, var: &str) -> Result<(Expression, Expression), IntegrationError> {
+        // 按 LIATE 顺序选择 u，排名靠前的作为 u
+        let left_rank = self.get_liate_rank(left, var);
+        let right_rank = self.get_liate_rank(right, var);
         
-        match self {
-            Expression::Multiply(left, right) => {
-                let (u, dv) = self.choose_u_dv(left, right)?;
-                
-                // 计算du
-                let du = u.differentiate(var);
-                
-                // 计算v (∫dv)
-                let v = dv.integrate_with_state(var, state)?;
-                
-                // 计算∫v·du
-                let v_du = Expression::multiply(v.clone(), du);
-                let v_du_int = v_du.integrate_with_state(var, state)?;
-                
-                // 最终结果：u·v - ∫v·du
-                let result = Expression::subtract(
-                    Expression::multiply(u, v),
-                    v_du_int
-                );
-                
-                state.pop_expression();
-                Ok(result)
-            }
-            _ => {
-                state.pop_expression();
-                Err(IntegrationError::NoMethodFound)
-            }
-        }
-    }
-
-    fn try_substitution(&self, _var: &str, _state: &mut IntegrationState) -> Result<Expression, IntegrationError> {
-        // TODO: 实现替换积分
-        // 使用 _var 和 _state 来避免未使用变量警告
-        let _ = _var;
-        let _ = _state;
-        Err(IntegrationError::NotImplemented)
-    }
-
-    fn choose_u_dv(&self, left: &Expression, right: &Expression) -> Result<(Expression, Expression), IntegrationError> {
-        // 评估哪个部分更适合作为u
-        let left_score = self.get_integration_difficulty_score(left);
-        let right_score = self.get_integration_difficulty_score(right);
-        
-        if left_score <= right_score {
+        if left_rank <= right_rank {
             Ok((left.clone(), right.clone()))
         } else {
             Ok((right.clone(), left.clone()))
         }
     }
 
-    fn get_integration_difficulty_score(&self, expr: &Expression) -> i32 {
+    // LIATE: 对数(0) < 反三角(1) < 代数(2) < 三角(3) < 指数(4)
+    fn get_liate_rank(&self, expr: &Expression, var: &str) -> i32 {
         match expr {
-            Expression::Constant(_) => 1,
-            Expression::Variable(_) => 2,
-            Expression::Add(_, _) | Expression::Subtract(_, _) => 3,
-            Expression::Multiply(_, _) => 4,
-            Expression::Divide(_, _) => 5,
-            Expression::Power(_, _) => 6,
-            Expression::Ln(_) => 7,
-            Expression::Sin(_) | Expression::Cos(_) => 4,
-            Expression::Tan(_) => 5,
+            Expression::Ln(_) | Expression::Log(_, _) => 0,
+            Expression::Arcsin(_) | Expression::Arccos(_) | Expression::Arctan(_) |
+            Expression::Arctanh(_) => 1,
+            Expression::Sin(_) | Expression::Cos(_) | Expression::Tan(_) |
+            Expression::Sec(_) | Expression::Csc(_) | Expression::Cot(_) |
+            Expression::Sinh(_) | Expression::Cosh(_) | Expression::Tanh(_) => 3,
             Expression::Exp(_) => 4,
-            _ => 10,
+            Expression::Power(base, _) if !base.contains_variable(var) => 4,
+            Expression::Power(base, _) | Expression::Negate(base) => self.get_liate_rank(base, var),
+            Expression::Add(left, right) |
+            Expression::Subtract(left, right) |
+            Expression::Multiply(left, right) |
+            Expression::Divide(left, right) => {
+                self.get_liate_rank(left, var).min(self.get_liate_rank(right, var))
+            }
+            _ => 2,
+        }
+    }
+}
+
+// 把乘积拆成常数系数和因子列表，分母中的因子记为负幂
+fn product_factors(expr: &Expression) -> (f64, Vec<Expression>) {
+    fn collect(expr: &Expression, inverse: bool, coeff: &mut f64, factors: &mut Vec<Expression>) {
+        match expr {
+            Expression::Constant(c) => {
+                if inverse { *coeff /= c } else { *coeff *= c }
+            }
+            Expression::Multiply(left, right) => {
+                collect(left, inverse, coeff, factors);
+                collect(right, inverse, coeff, factors);
+            }
+            Expression::Negate(inner) => {
+                *coeff = -*coeff;
+                collect(inner, inverse, coeff, factors);
+            }
+            Expression::Divide(left, right) => {
+                collect(left, inverse, coeff, factors);
+                collect(right, !inverse, coeff, factors);
+            }
+            _ if inverse => {
+                let reciprocal = match expr {
+                    Expression::Power(base, exponent) => match exponent.constant_value() {
+                        Some(n) => Expression::power((**base).clone(), Expression::constant(-n)),
+                        None => Expression::power(expr.clone(), Expression::constant(-1.0)),
+                    },
+                    _ => Expression::power(expr.clone(), Expression::constant(-1.0)),
+                };
+                factors.push(reciprocal.simplify());
+            }
+            _ => factors.push(expr.simplify()),
+        }
+    }
+
+    let mut coeff = 1.0;
+    let mut factors = Vec::new();
+    collect(expr, false, &mut coeff, &mut factors);
+    (coeff, factors)
+}
+
+// expr 关于 var 为线性（a*var + b，a ≠ 0 且不含 var）时返回 a
+fn linear_coefficient(expr: &Expression, var: &str) -> Option<Expression> {
+    let a = expr.differentiate(var).simplify();
+    (!a.contains_variable(var) && a.constant_value() != Some(0.0)).then_some(a)
+}
+
+// expr / a；a 的倒数是整数时写成乘积，如 e^(x/2)/0.5 => 2 * e^(x/2)
+fn divide_by_coefficient(expr: Expression, a: Expression) -> Expression {
+    match a.constant_value() {
+        Some(c) if (1.0 / c).fract() == 0.0 => scaled(1.0 / c, expr),
+        _ => Expression::divide(expr, a),
+    }
+}
+
+// 选一个不与表达式中已有变量冲突的换元变量名
+fn fresh_variable(expr: &Expression) -> String {
+    (0..)
+        .map(|i| if i == 0 { "u".to_string() } else { format!("u{}", i) })
+        .find(|name| !expr.contains_variable(name))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse;
+    use std::collections::HashMap;
+
+    fn integral(input: &str) -> String {
+        parse(input).unwrap().integrate("x").unwrap().to_string()
+    }
+
+    // 在几个采样点上比较结果的导数与被积函数
+    fn assert_antiderivative(input: &str) {
+        assert_antiderivative_at(input, &[0.3, 0.7, 1.3, 2.1]);
+    }
+
+    fn assert_antiderivative_at(input: &str, points: &[f64]) {
+        let integrand = parse(input).unwrap();
+        let derivative = integrand.integrate("x").unwrap().derivative("x");
+        for &x in points {
+            let bindings = HashMap::from([("x".to_string(), x)]);
+            let expected = integrand.evaluate(&bindings).unwrap();
+            let actual = derivative.evaluate(&bindings).unwrap();
+            assert!((expected - actual).abs() < 1e-9, "d/dx ∫{} at {}: {} != {}", input, x, actual, expected);
+        }
+    }
+
+    #[test]
+    fn integrates_by_parts() {
+        assert_eq!(integral("x*e^x"), "x * exp(x) - exp(x)");
+        assert_eq!(integral("x*ln(x)"), "ln(x) * x^2 / 2 - x^2 / 4");
+        assert_eq!(integral("ln(x)"), "x * ln(x) - x");
+        assert_eq!(integral("x*sin(x)"), "-x * cos(x) + sin(x)");
+        for input in ["x*e^x", "x*ln(x)", "ln(x)*x", "x^2*ln(x)", "x^2*e^x", "x*cos(x)", "arctan(x)"] {
+            assert_antiderivative(input);
+        }
+    }
+
+    #[test]
+    fn integrates_inverse_trig_forms() {
+        assert_eq!(integral("1/(x^2+1)"), "arctan(x)");
+        assert_eq!(integral("1/(x^2+4)"), "arctan(x / sqrt(4)) / sqrt(4)");
+        assert_eq!(integral("1/sqrt(1-x^2)"), "arcsin(x)");
+        assert_eq!(integral("1/(1-x^2)"), "arctanh(x)");
+        assert_antiderivative("1/(4+x^2)");
+    }
+
+    // a < 0 不能用 arctan(x/√a)/√a，交给部分分式
+    #[test]
+    fn reciprocal_of_x_squared_minus_constant_is_not_arctan() {
+        let result = integral("1/(x^2-4)");
+        assert!(!result.contains("arctan") && !result.contains("NaN"), "{}", result);
+        assert_antiderivative_at("1/(x^2-4)", &[2.5, 3.0, 7.0]);
+        assert_antiderivative_at("1/(x^2+(-4))", &[2.5, 3.0, 7.0]);
+    }
+
+    #[test]
+    fn reciprocal_integrates_to_log_of_absolute_value() {
+        assert_eq!(integral("1/x"), "ln(abs(x))");
+        assert_eq!(integral("x^-1"), "ln(abs(x))");
+        assert_antiderivative_at("1/x", &[-2.0, -0.5, 0.5, 2.0]);
+    }
+
+    #[test]
+    fn integrates_reciprocal_trig_functions() {
+        assert_eq!(integral("sec(x)"), "ln(abs(sec(x) + tan(x)))");
+        assert_eq!(integral("csc(x)"), "-ln(abs(csc(x) + cot(x)))");
+        assert_eq!(integral("cot(x)"), "ln(abs(sin(x)))");
+        for input in ["sec(x)", "csc(x)", "cot(x)"] {
+            assert_antiderivative(input);
+        }
+    }
+
+    #[test]
+    fn integrates_squared_secant_and_cosecant() {
+        assert_eq!(integral("sec(x)^2"), "tan(x)");
+        assert_eq!(integral("csc(x)^2"), "-cot(x)");
+        assert_eq!(integral("sec(x)*tan(x)"), "sec(x)");
+        for input in ["sec(x)^2", "csc(x)^2", "sec(x)*tan(x)"] {
+            assert_antiderivative(input);
+        }
+    }
+
+    #[test]
+    fn integrates_powers_of_linear_bases() {
+        assert_eq!(integral("(2*x+1)^3"), "(2 * x + 1)^4 / 8");
+        let reversed = parse("(3-x)^2").unwrap().integrate("x").unwrap().simplify();
+        assert_eq!(reversed.to_string(), "-(3 - x)^3 / 3");
+        assert_eq!(integral("(2*x+1)^-1"), "ln(abs(2 * x + 1)) / 2");
+        for input in ["(2*x+1)^3", "(2*x+1)^5", "(3-x)^2", "(x/2+1)^-2", "sqrt(2*x+1)"] {
+            assert_antiderivative(input);
+        }
+        assert_antiderivative_at("(2*x+1)^-1", &[-3.0, -1.0, 0.5, 2.0]);
+        assert!(parse("(x^2+1)^3").unwrap().integrate("x").is_err());
+    }
+
+    #[test]
+    fn partial_fraction_logs_take_the_absolute_value() {
+        assert_eq!(integral("1/(x*(x+1))"), "ln(abs(x)) - ln(abs(x + 1))");
+        assert_antiderivative_at("1/(x^2-4)", &[-3.0, -1.0, 0.5, 3.0]);
+        assert_antiderivative_at("(x^2+1)/(x-1)", &[-2.0, 0.5, 3.0]);
+    }
+
+    #[test]
+    fn integrates_tangent_and_reciprocal_squares() {
+        let simplified = |input: &str| parse(input).unwrap().integrate("x").unwrap().simplify().to_string();
+        assert_eq!(integral("tan(x)"), "-ln(abs(cos(x)))");
+        assert_eq!(simplified("tan(2*x)"), "-0.5 * ln(abs(cos(2 * x)))");
+        assert_eq!(integral("1/cos(x)^2"), "tan(x)");
+        assert_eq!(integral("1/sin(x)^2"), "-cot(x)");
+        for input in ["tan(2*x)", "tan(3*x + 1)", "1/cos(x)^2", "1/sin(x)^2"] {
+            assert_antiderivative(input);
+        }
+        assert_antiderivative_at("tan(x)", &[2.0, 2.5, 4.0]);
+    }
+
+    #[test]
+    fn integrates_by_substitution() {
+        use crate::calculus::IntegrationMethod;
+        let simplified = |input: &str| parse(input).unwrap().integrate("x").unwrap().simplify().to_string();
+        assert_eq!(simplified("2*x*cos(x^2)"), "sin(x^2)");
+        assert_eq!(simplified("x*e^(x^2)"), "0.5 * exp(x^2)");
+        assert_eq!(simplified("ln(x)/x"), "ln(x)^2 / 2");
+        assert_eq!(simplified("-ln(x)/x"), "-ln(x)^2 / 2");
+        for input in ["2*x*cos(x^2)", "x*e^(x^2)"] {
+            let forced = parse(input).unwrap().integrate_using("x", IntegrationMethod::Substitution).unwrap();
+            assert_eq!(forced.simplify().to_string(), simplified(input));
+            assert_antiderivative(input);
         }
     }
 }
@@ -1,10 +1,19 @@
 use crate::Expression;
 use super::IntegrationError;
+use std::mem::discriminant;
+
+// Inside patterns "x" stands for the integration variable; any other
+// variable is a wildcard matching an expression free of it.
+const PATTERN_VAR: &str = "x";
+
+type Bindings = Vec<(String, Expression)>;
 
 #[derive(Clone)]
 pub struct IntegrationRule {
     pub pattern: Expression,
     pub result: Expression,
+    // Extra requirement on the wildcard bindings, checked after the pattern matches
+    pub condition: fn(&Bindings) -> bool,
 }
 
 pub struct IntegrationTable {
@@ -19,6 +28,24 @@ impl IntegrationTable {
     }
 
     fn initialize_rules(&mut self) {
+        // Reciprocal rules, checked before the power rule so that n = -1 never reaches it.
+        // ∫1/x = ln|x| holds on both sides of 0
+        self.add_rule(
+            Expression::power(
+                Expression::variable("x"),
+                Expression::constant(-1.0)
+            ),
+            Expression::ln(Expression::abs(Expression::variable("x")))
+        );
+
+        self.add_rule(
+            Expression::divide(
+                Expression::constant(1.0),
+                Expression::variable("x")
+            ),
+            Expression::ln(Expression::abs(Expression::variable("x")))
+        );
+
         // Basic power rules
         self.add_rule(
             Expression::power(
@@ -37,10 +64,7 @@ impl IntegrationTable {
         // Trigonometric functions
         self.add_rule(
             Expression::sin(Expression::variable("x")),
-            Expression::multiply(
-                Expression::constant(-1.0),
-                Expression::cos(Expression::variable("x"))
-            )
+            Expression::negate(Expression::cos(Expression::variable("x")))
         );
 
         self.add_rule(
@@ -48,11 +72,64 @@ impl IntegrationTable {
             Expression::sin(Expression::variable("x"))
         );
 
+        // ∫tan = -ln|cos|, ∫sec = ln|sec + tan|, ∫csc = -ln|csc + cot|, ∫cot = ln|sin|
         self.add_rule(
             Expression::tan(Expression::variable("x")),
-            Expression::multiply(
-                Expression::constant(-1.0),
-                Expression::ln(Expression::cos(Expression::variable("x")))
+            Expression::negate(Expression::ln(Expression::abs(Expression::cos(Expression::variable("x")))))
+        );
+
+        self.add_rule(
+            Expression::sec(Expression::variable("x")),
+            Expression::ln(Expression::abs(Expression::add(
+                Expression::sec(Expression::variable("x")),
+                Expression::tan(Expression::variable("x"))
+            )))
+        );
+
+        self.add_rule(
+            Expression::csc(Expression::variable("x")),
+            Expression::negate(Expression::ln(Expression::abs(Expression::add(
+                Expression::csc(Expression::variable("x")),
+                Expression::cot(Expression::variable("x"))
+            ))))
+        );
+
+        self.add_rule(
+            Expression::cot(Expression::variable("x")),
+            Expression::ln(Expression::abs(Expression::sin(Expression::variable("x"))))
+        );
+
+        // ∫sec(x)^2 = tan(x) and ∫csc(x)^2 = -cot(x), also written as 1/cos(x)^2 and 1/sin(x)^2
+        // in both the Divide and negative Power shapes
+        let trig_power = |f: fn(Expression) -> Expression, n: f64| {
+            Expression::power(f(Expression::variable("x")), Expression::constant(n))
+        };
+        for pattern in [
+            Expression::divide(Expression::constant(1.0), trig_power(Expression::cos, 2.0)),
+            trig_power(Expression::cos, -2.0),
+            trig_power(Expression::sec, 2.0),
+        ] {
+            self.add_rule(pattern, Expression::tan(Expression::variable("x")));
+        }
+        for pattern in [
+            Expression::divide(Expression::constant(1.0), trig_power(Expression::sin, 2.0)),
+            trig_power(Expression::sin, -2.0),
+            trig_power(Expression::csc, 2.0),
+        ] {
+            self.add_rule(
+                pattern,
+                Expression::negate(Expression::cot(Expression::variable("x")))
+            );
+        }
+
+        self.add_rule(
+            Expression::sqrt(Expression::variable("x")),
+            Expression::divide(
+                Expression::multiply(
+                    Expression::constant(2.0),
+                    Expression::power(Expression::variable("x"), Expression::constant(1.5))
+                ),
+                Expression::constant(3.0)
             )
         );
 
@@ -88,28 +165,123 @@ impl IntegrationTable {
             Expression::tanh(Expression::variable("x")),
             Expression::ln(Expression::cosh(Expression::variable("x")))
         );
+
+        // Inverse trigonometric and hyperbolic forms, matched on the Divide shape
+        let x_squared = || Expression::power(Expression::variable("x"), Expression::constant(2.0));
+        let one_minus_x_squared = || Expression::subtract(Expression::constant(1.0), x_squared());
+        let reciprocal = |denominator| Expression::divide(Expression::constant(1.0), denominator);
+
+        for denominator in [
+            Expression::add(x_squared(), Expression::constant(1.0)),
+            Expression::add(Expression::constant(1.0), x_squared()),
+        ] {
+            self.add_rule(reciprocal(denominator), Expression::arctan(Expression::variable("x")));
+        }
+
+        // 1/(x^2 + a) = arctan(x/√a)/√a, with a = a^2 in the usual notation. Only for a > 0:
+        // for a < 0 √a is undefined and the integral is a logarithm, left to partial fractions
+        let sqrt_a = || Expression::sqrt(Expression::variable("a"));
+        for denominator in [
+            Expression::add(x_squared(), Expression::variable("a")),
+            Expression::add(Expression::variable("a"), x_squared()),
+        ] {
+            self.add_rule_if(
+                |bindings| bound(bindings, "a").is_some_and(is_positive),
+                reciprocal(denominator),
+                Expression::divide(
+                    Expression::arctan(Expression::divide(Expression::variable("x"), sqrt_a())),
+                    sqrt_a()
+                )
+            );
+        }
+
+        for denominator in [
+            Expression::sqrt(one_minus_x_squared()),
+            Expression::power(one_minus_x_squared(), Expression::constant(0.5)),
+            Expression::root(one_minus_x_squared(), Expression::constant(2.0)),
+        ] {
+            self.add_rule(reciprocal(denominator), Expression::arcsin(Expression::variable("x")));
+        }
+
+        self.add_rule(
+            Expression::power(one_minus_x_squared(), Expression::constant(-0.5)),
+            Expression::arcsin(Expression::variable("x"))
+        );
+
+        self.add_rule(
+            reciprocal(one_minus_x_squared()),
+            Expression::arctanh(Expression::variable("x"))
+        );
     }
 
     fn add_rule(&mut self, pattern: Expression, result: Expression) {
-        self.rules.push(IntegrationRule { pattern, result });
+        self.add_rule_if(|_| true, pattern, result);
+    }
+
+    fn add_rule_if(&mut self, condition: fn(&Bindings) -> bool, pattern: Expression, result: Expression) {
+        self.rules.push(IntegrationRule { pattern, result, condition });
     }
 
     pub fn lookup(&self, expr: &Expression, var: &str) -> Option<Result<Expression, IntegrationError>> {
         for rule in &self.rules {
-            if self.matches(&rule.pattern, expr, var) {
-                return Some(Ok(self.apply_rule(&rule.result, expr, var)));
+            let mut bindings = Bindings::new();
+            if self.matches(&rule.pattern, expr, var, &mut bindings) && (rule.condition)(&bindings) {
+                return Some(Ok(self.apply_rule(&rule.result, var, &bindings)));
             }
         }
         None
     }
 
-    fn matches(&self, _pattern: &Expression, _expr: &Expression, _var: &str) -> bool {
-        // TODO: 实现模式匹配
-        false
+    fn matches(&self, pattern: &Expression, expr: &Expression, var: &str, bindings: &mut Bindings) -> bool {
+        match pattern {
+            Expression::Variable(name) if name == PATTERN_VAR => {
+                matches!(expr, Expression::Variable(v) if v == var)
+            }
+            Expression::Variable(name) => {
+                if expr.contains_variable(var) {
+                    return false;
+                }
+                if let Some((_, bound)) = bindings.iter().find(|(n, _)| n == name) {
+                    return bound == expr;
+                }
+                bindings.push((name.clone(), expr.clone()));
+                true
+            }
+            Expression::Constant(c) => matches!(expr, Expression::Constant(e) if e == c),
+            _ => {
+                if discriminant(pattern) != discriminant(expr) {
+                    return false;
+                }
+                pattern.children()
+                    .into_iter()
+                    .zip(expr.children())
+                    .all(|(p, e)| self.matches(p, e, var, bindings))
+            }
+        }
+    }
+
+    fn apply_rule(&self, result: &Expression, var: &str, bindings: &Bindings) -> Expression {
+        match result {
+            Expression::Variable(name) if name == PATTERN_VAR => Expression::variable(var),
+            Expression::Variable(name) => bindings
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, bound)| bound.clone())
+                .unwrap_or_else(|| result.clone()),
+            _ => result.map_children(|child| self.apply_rule(child, var, bindings)),
+        }
     }
+}
+
+fn bound<'a>(bindings: &'a Bindings, name: &str) -> Option<&'a Expression> {
+    bindings.iter().find(|(n, _)| n == name).map(|(_, bound)| bound)
+}
 
-    fn apply_rule(&self, result: &Expression, _expr: &Expression, _var: &str) -> Expression {
-        // TODO: 实现规则应用
-        result.clone()
+// Known to be positive: a positive constant or a square u^2 (taking u ≠ 0)
+fn is_positive(expr: &Expression) -> bool {
+    match expr {
+        Expression::Constant(c) => *c > 0.0,
+        Expression::Power(_, exponent) => **exponent == Expression::Constant(2.0),
+        _ => false,
     }
 }
@@ -31,42 +31,7 @@ impl IntegrationState {
         if self.depth >= self.max_depth {
             return true;
         }
-        self.visited_expressions.iter().any(|visited| self.is_similar(visited, expr))
-    }
-
-    fn is_similar(&self, expr1: &Expression, expr2: &Expression) -> bool {
-        // 基本相等检查
-        if expr1 == expr2 {
-            return true;
-        }
-
-        // 结构相似性检查
-        match (expr1, expr2) {
-            // 检查乘法交换律
-            (Expression::Multiply(a1, b1), Expression::Multiply(a2, b2)) => {
-                (self.is_similar(a1, a2) && self.is_similar(b1, b2)) ||
-                (self.is_similar(a1, b2) && self.is_similar(b1, a2))
-            }
-            // 检查加法交换律
-            (Expression::Add(a1, b1), Expression::Add(a2, b2)) => {
-                (self.is_similar(a1, a2) && self.is_similar(b1, b2)) ||
-                (self.is_similar(a1, b2) && self.is_similar(b1, a2))
-            }
-            // 检查减法（考虑可能的重排）
-            (Expression::Subtract(a1, b1), Expression::Subtract(a2, b2)) => {
-                self.is_similar(a1, a2) && self.is_similar(b1, b2)
-            }
-            // 检查函数调用
-            (Expression::Sin(a1), Expression::Sin(a2)) |
-            (Expression::Cos(a1), Expression::Cos(a2)) |
-            (Expression::Tan(a1), Expression::Tan(a2)) |
-            (Expression::Exp(a1), Expression::Exp(a2)) |
-            (Expression::Ln(a1), Expression::Ln(a2)) => {
-                self.is_similar(a1, a2)
-            }
-            // 其他情况认为不相似
-            _ => false,
-        }
+        self.visited_expressions.iter().any(|visited| visited.equivalent(expr))
     }
 
     pub fn push_expression(&mut self, expr: Expression) {
@@ -0,0 +1,116 @@
+use crate::Expression;
+use crate::evaluate::EvalError;
+use super::differentiate::Differentiate;
+use super::LimitError;
+use std::collections::HashMap;
+
+// 洛必达法则最多连续使用的次数
+const LHOPITAL_MAX_DEPTH: usize = 8;
+// 判断趋于无穷时的符号，在 point 两侧取的偏移
+const PROBE_OFFSET: f64 = 1e-9;
+
+impl Expression {
+    // 求 var → point 时的极限。能直接代入时代入；Divide 出现 0/0 或 ∞/∞ 时
+    // 对分子分母分别求导再求极限（洛必达法则）。极限为无穷大时返回 ±inf 常数，
+    // 两侧趋于不同的无穷大、或无法判断时返回错误
+    pub fn limit(&self, var: &str, point: f64) -> Result<Expression, LimitError> {
+        limit_value(&self.simplify(), var, point, LHOPITAL_MAX_DEPTH).map(Expression::constant)
+    }
+}
+
+fn limit_value(expr: &Expression, var: &str, point: f64, depth: usize) -> Result<f64, LimitError> {
+    if let Ok(value) = evaluate_at(expr, var, point) {
+        return Ok(value);
+    }
+
+    match expr {
+        Expression::Divide(numerator, denominator) => {
+            let top = limit_value(numerator, var, point, depth)?;
+            let bottom = limit_value(denominator, var, point, depth)?;
+            let indeterminate = (top == 0.0 && bottom == 0.0) || (top.is_infinite() && bottom.is_infinite());
+            if indeterminate {
+                if depth == 0 {
+                    return Err(LimitError::LhopitalDepthExceeded { expr: expr.clone(), point });
+                }
+                let ratio = Expression::divide(numerator.differentiate(var), denominator.differentiate(var));
+                return limit_value(&ratio.simplify(), var, point, depth - 1);
+            }
+            if bottom == 0.0 {
+                return infinite_sign(expr, var, point);
+            }
+            Ok(top / bottom)
+        }
+        // u → 0⁺ 时 ln(u) → -∞
+        Expression::Ln(arg) if limit_value(arg, var, point, depth)? == 0.0 => Ok(f64::NEG_INFINITY),
+        _ => {
+            // 连续函数：先求各参数的极限，再代入
+            let mut failure = None;
+            let substituted = expr.map_children(|child| match limit_value(child, var, point, depth) {
+                Ok(value) => Expression::constant(value),
+                Err(error) => {
+                    failure.get_or_insert(error);
+                    child.clone()
+                }
+            });
+            if let Some(error) = failure {
+                return Err(error);
+            }
+            // 0 * ∞、∞ - ∞ 等未定式求值得到 NaN，这里不做变形，直接报错；
+            // 超出定义域（如 sqrt(x - 5) 在 0 处）则是极限不存在
+            match substituted.evaluate(&HashMap::new()) {
+                Ok(value) => Ok(value),
+                Err(EvalError::DivisionByZero) => infinite_sign(expr, var, point),
+                Err(EvalError::DomainError(_)) => Err(LimitError::Undefined { expr: expr.clone(), point }),
+                Err(_) => Err(LimitError::Indeterminate { expr: expr.clone(), point }),
+            }
+        }
+    }
+}
+
+// 在 point 两侧附近求值：两侧（或唯一有定义的一侧）同号时认为极限是该符号的无穷大
+fn infinite_sign(expr: &Expression, var: &str, point: f64) -> Result<f64, LimitError> {
+    let sides: Vec<f64> = [point - PROBE_OFFSET, point + PROBE_OFFSET]
+        .iter()
+        .filter_map(|x| evaluate_at(expr, var, *x).ok())
+        .collect();
+    match sides.as_slice() {
+        [] => Err(LimitError::Undefined { expr: expr.clone(), point }),
+        [first, rest @ ..] if rest.iter().all(|v| v.signum() == first.signum()) => {
+            Ok(f64::INFINITY.copysign(*first))
+        }
+        _ => Err(LimitError::OneSidedLimitsDiffer { expr: expr.clone(), point }),
+    }
+}
+
+fn evaluate_at(expr: &Expression, var: &str, x: f64) -> Result<f64, EvalError> {
+    let mut bindings = HashMap::new();
+    bindings.insert(var.to_string(), x);
+    expr.evaluate(&bindings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LimitError;
+    use crate::{parse, Expression};
+
+    fn limit(input: &str, point: f64) -> Result<Expression, LimitError> {
+        parse(input).unwrap().limit("x", point)
+    }
+
+    #[test]
+    fn resolves_indeterminate_quotients() {
+        assert_eq!(limit("sin(x)/x", 0.0), Ok(Expression::constant(1.0)));
+        assert_eq!(limit("(1 - cos(x))/x^2", 0.0), Ok(Expression::constant(0.5)));
+        assert_eq!(limit("(x^2 - 1)/(x - 1)", 1.0), Ok(Expression::constant(2.0)));
+        assert_eq!(limit("x^2 + 1", 2.0), Ok(Expression::constant(5.0)));
+        assert_eq!(limit("1/x^2", 0.0), Ok(Expression::constant(f64::INFINITY)));
+    }
+
+    #[test]
+    fn reports_typed_errors() {
+        assert!(matches!(limit("1/x", 0.0), Err(LimitError::OneSidedLimitsDiffer { point, .. }) if point == 0.0));
+        assert!(matches!(limit("sin(x)^9/x^9", 0.0), Err(LimitError::LhopitalDepthExceeded { .. })));
+        assert!(matches!(limit("sqrt(x - 5)", 0.0), Err(LimitError::Undefined { point, .. }) if point == 0.0));
+        assert!(matches!(limit("ln(x - 5)", 1.0), Err(LimitError::Undefined { .. })));
+    }
+}
@@ -0,0 +1,88 @@
+use crate::Expression;
+use crate::polynomial::{poly_derivative, poly_divmod, poly_eval, poly_real_roots, poly_to_expression};
+use super::IntegrationError;
+
+// 两个根之差小于该值时视为重根
+const ROOT_SEPARATION: f64 = 1e-6;
+
+// P/Q 的部分分式分解：商多项式的系数，以及每个 A/(x - r) 的 (A, r)
+pub(super) struct PartialFractions {
+    pub quotient: Vec<f64>,
+    pub terms: Vec<(f64, f64)>,
+}
+
+impl Expression {
+    // 把关于 var 的有理函数分解为多项式与 A/(x - r) 之和，
+    // 目前只支持分母为互异实一次因子的乘积
+    pub fn partial_fractions(&self, var: &str) -> Result<Expression, IntegrationError> {
+        let decomposition = self.decompose_rational(var)?;
+        let x = Expression::variable(var);
+        let mut result = match decomposition.quotient.as_slice() {
+            [c] if *c == 0.0 => None,
+            quotient => Some(poly_to_expression(quotient, var)),
+        };
+        for (a, r) in decomposition.terms {
+            let fraction = Expression::divide(Expression::constant(a.abs()), linear_factor(&x, r));
+            result = Some(match result {
+                None if a < 0.0 => Expression::divide(Expression::constant(a), linear_factor(&x, r)),
+                None => fraction,
+                Some(acc) if a < 0.0 => Expression::subtract(acc, fraction),
+                Some(acc) => Expression::add(acc, fraction),
+            });
+        }
+        Ok(result.unwrap_or(Expression::constant(0.0)))
+    }
+
+    pub(super) fn decompose_rational(&self, var: &str) -> Result<PartialFractions, IntegrationError> {
+        let not_rational = || IntegrationError::InvalidInput(format!("{} is not a ratio of polynomials in {}", self, var));
+        let Expression::Divide(num, den) = self else {
+            return Err(not_rational());
+        };
+        let num = num.poly_coeffs(var).ok_or_else(not_rational)?;
+        let den = den.poly_coeffs(var).ok_or_else(not_rational)?;
+        if den.len() < 2 {
+            return Err(not_rational());
+        }
+
+        let (quotient, remainder) = poly_divmod(&num, &den);
+        let roots = poly_real_roots(&den).ok_or_else(|| {
+            IntegrationError::UnsupportedOperation("denominator has irreducible quadratic factors".to_string())
+        })?;
+        for (i, a) in roots.iter().enumerate() {
+            if roots[i + 1..].iter().any(|b| (a - b).abs() < ROOT_SEPARATION) {
+                return Err(IntegrationError::UnsupportedOperation("denominator has repeated factors".to_string()));
+            }
+        }
+
+        // 互异单根 r 处的系数 A = R(r) / Q'(r)
+        let derivative = poly_derivative(&den);
+        let mut terms: Vec<(f64, f64)> = roots
+            .into_iter()
+            .map(|r| {
+                let r = tidy(r);
+                (tidy(poly_eval(&remainder, r) / poly_eval(&derivative, r)), r)
+            })
+            .filter(|(a, _)| *a != 0.0)
+            .collect();
+        terms.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        Ok(PartialFractions { quotient: quotient.into_iter().map(tidy).collect(), terms })
+    }
+}
+
+// x - r，r 为负数时写成 x + |r|
+pub(super) fn linear_factor(x: &Expression, r: f64) -> Expression {
+    if r == 0.0 {
+        x.clone()
+    } else if r < 0.0 {
+        Expression::add(x.clone(), Expression::constant(-r))
+    } else {
+        Expression::subtract(x.clone(), Expression::constant(r))
+    }
+}
+
+// 消去数值迭代留下的舍入误差，如 0.49999999999999994 => 0.5
+fn tidy(value: f64) -> f64 {
+    let rounded = (value * 1e12).round() / 1e12;
+    if rounded == 0.0 { 0.0 } else { rounded }
+}
@@ -0,0 +1,145 @@
+use crate::evaluate::{BinaryOp, EvalError, UnaryOp};
+use crate::expression::Expression;
+pub use num_complex::Complex64;
+use std::collections::HashMap;
+
+impl BinaryOp {
+    // 复数上的运算取主值分支
+    fn apply_complex(self, a: Complex64, b: Complex64) -> Result<Complex64, EvalError> {
+        let zero = Complex64::new(0.0, 0.0);
+        match self {
+            BinaryOp::Add => Ok(a + b),
+            BinaryOp::Subtract => Ok(a - b),
+            BinaryOp::Multiply => Ok(a * b),
+            BinaryOp::Divide => {
+                if b == zero {
+                    return Err(EvalError::DivisionByZero);
+                }
+                Ok(a / b)
+            }
+            BinaryOp::Power => {
+                // powc 经由 ln(a)，底数为 0 时单独处理
+                if a == zero {
+                    return match b.re {
+                        _ if b == zero => Ok(Complex64::new(1.0, 0.0)),
+                        re if re > 0.0 => Ok(zero),
+                        _ => Err(EvalError::DivisionByZero),
+                    };
+                }
+                Ok(a.powc(b))
+            }
+            BinaryOp::Root => {
+                if b == zero {
+                    return Err(EvalError::DivisionByZero);
+                }
+                if a == zero {
+                    return Ok(zero);
+                }
+                Ok(a.powc(b.inv()))
+            }
+            // a 为底数，b 为真数
+            BinaryOp::Log => {
+                if a == zero || a == Complex64::new(1.0, 0.0) || b == zero {
+                    return Err(EvalError::DomainError(format!("log({}, {})", b, a)));
+                }
+                Ok(b.ln() / a.ln())
+            }
+            BinaryOp::Polygamma => {
+                if a.im != 0.0 || b.im != 0.0 {
+                    return Err(EvalError::DomainError(format!("polygamma({}, {})", a, b)));
+                }
+                Ok(Complex64::new(self.apply(a.re, b.re)?, 0.0))
+            }
+            // 复数之间没有大小关系
+            BinaryOp::Min | BinaryOp::Max => {
+                if a.im != 0.0 || b.im != 0.0 {
+                    return Err(EvalError::DomainError(format!("{:?} of complex {}, {}", self, a, b)));
+                }
+                Ok(Complex64::new(self.apply(a.re, b.re)?, 0.0))
+            }
+        }
+    }
+}
+
+impl UnaryOp {
+    fn apply_complex(self, z: Complex64) -> Result<Complex64, EvalError> {
+        let zero = Complex64::new(0.0, 0.0);
+        let reciprocal = |w: Complex64| {
+            if w == zero { Err(EvalError::DivisionByZero) } else { Ok(w.inv()) }
+        };
+        match self {
+            UnaryOp::Sin => Ok(z.sin()),
+            UnaryOp::Cos => Ok(z.cos()),
+            UnaryOp::Tan => Ok(z.tan()),
+            UnaryOp::Sec => reciprocal(z.cos()),
+            UnaryOp::Csc => reciprocal(z.sin()),
+            UnaryOp::Cot => reciprocal(z.tan()),
+            UnaryOp::Arcsin => Ok(z.asin()),
+            UnaryOp::Arccos => Ok(z.acos()),
+            UnaryOp::Arctan => Ok(z.atan()),
+            UnaryOp::Sqrt => Ok(z.sqrt()),
+            UnaryOp::Negate => Ok(-z),
+            UnaryOp::Exp => Ok(z.exp()),
+            UnaryOp::Ln => {
+                if z == zero {
+                    return Err(EvalError::DomainError("ln(0)".to_string()));
+                }
+                Ok(z.ln())
+            }
+            UnaryOp::Sinh => Ok(z.sinh()),
+            UnaryOp::Cosh => Ok(z.cosh()),
+            UnaryOp::Tanh => Ok(z.tanh()),
+            UnaryOp::Arctanh => Ok(z.atanh()),
+            UnaryOp::Abs => Ok(Complex64::new(z.norm(), 0.0)),
+            // 阶乘和阶跃函数只在实轴上定义
+            UnaryOp::Factorial | UnaryOp::Heaviside => {
+                if z.im != 0.0 {
+                    return Err(EvalError::DomainError(format!("{:?} of complex {}", self, z)));
+                }
+                Ok(Complex64::new(self.apply(z.re)?, 0.0))
+            }
+        }
+    }
+}
+
+impl Expression {
+    // 在复数域上求值，ln、sqrt、乘方等取主值：sqrt(-1) = i，ln(-1) = iπ
+    pub fn evaluate_complex(&self, bindings: &HashMap<String, Complex64>) -> Result<Complex64, EvalError> {
+        let value = match self {
+            Expression::Constant(c) => Complex64::new(*c, 0.0),
+            Expression::Variable(name) => match bindings.get(name) {
+                Some(value) => *value,
+                None => return Err(EvalError::UnboundVariable(name.clone())),
+            },
+            Expression::Piecewise(condition, if_nonnegative, otherwise) => {
+                let c = condition.evaluate_complex(bindings)?;
+                if c.im != 0.0 {
+                    return Err(EvalError::DomainError(format!("piecewise condition {} is not real", c)));
+                }
+                if c.re >= 0.0 {
+                    if_nonnegative.evaluate_complex(bindings)?
+                } else {
+                    otherwise.evaluate_complex(bindings)?
+                }
+            }
+            Expression::Clamp(expr, lo, hi) => {
+                let lower = BinaryOp::Max.apply_complex(expr.evaluate_complex(bindings)?, lo.evaluate_complex(bindings)?)?;
+                BinaryOp::Min.apply_complex(lower, hi.evaluate_complex(bindings)?)?
+            }
+            _ => {
+                if let Some((op, left, right)) = self.binary_op() {
+                    op.apply_complex(left.evaluate_complex(bindings)?, right.evaluate_complex(bindings)?)?
+                } else if let Some((op, expr)) = self.unary_op() {
+                    op.apply_complex(expr.evaluate_complex(bindings)?)?
+                } else {
+                    unreachable!("every composite node is a unary or binary operation")
+                }
+            }
+        };
+
+        if value.is_nan() {
+            return Err(EvalError::DomainError(self.to_string()));
+        }
+        Ok(value)
+    }
+}
@@ -0,0 +1,117 @@
+use crate::expression::Expression;
+use std::collections::{BTreeSet, HashMap};
+
+impl Expression {
+    // 公共子表达式消去：出现不止一次的非叶子子树提成绑定 (名字, 表达式)，
+    // 原表达式里改为引用新变量 t0、t1……（跳过已有的变量名）。
+    // 绑定按依赖顺序排列，后面的绑定可以引用前面的名字；从后往前依次 substitute 回去即得原表达式
+    pub fn cse(&self) -> (Expression, Vec<(String, Expression)>) {
+        let mut counts: HashMap<&Expression, usize> = HashMap::new();
+        let mut pending = vec![self];
+        while let Some(expr) = pending.pop() {
+            let children = expr.children();
+            if !children.is_empty() {
+                *counts.entry(expr).or_default() += 1;
+            }
+            pending.extend(children);
+        }
+
+        let mut builder = CseBuilder {
+            counts,
+            names: HashMap::new(),
+            fresh: FreshNames::new(self),
+            bindings: Vec::new(),
+        };
+        let rewritten = builder.rewrite(self);
+        let mut bindings = builder.bindings;
+        let mut rewritten = inline_single_uses(rewritten, &mut bindings);
+
+        // 代回之后编号不再连续，按顺序重新命名。新编号不大于旧编号，依次替换不会把名字混在一起
+        let mut fresh = FreshNames::new(self);
+        for i in 0..bindings.len() {
+            let renamed = Expression::variable(&fresh.next());
+            let old = std::mem::replace(&mut bindings[i].0, renamed.to_string());
+            rewritten = rewritten.substitute(&old, &renamed);
+            for (_, later) in bindings[i + 1..].iter_mut() {
+                *later = later.substitute(&old, &renamed);
+            }
+        }
+        (rewritten, bindings)
+    }
+}
+
+// 依次生成 t0、t1……，跳过表达式里已有的变量名
+struct FreshNames {
+    taken: BTreeSet<String>,
+    next_index: usize,
+}
+
+impl FreshNames {
+    fn new(expr: &Expression) -> Self {
+        FreshNames { taken: expr.variables(), next_index: 0 }
+    }
+
+    fn next(&mut self) -> String {
+        loop {
+            let name = format!("t{}", self.next_index);
+            self.next_index += 1;
+            if !self.taken.contains(&name) {
+                return name;
+            }
+        }
+    }
+}
+
+struct CseBuilder<'a> {
+    counts: HashMap<&'a Expression, usize>,
+    names: HashMap<&'a Expression, String>,
+    fresh: FreshNames,
+    bindings: Vec<(String, Expression)>,
+}
+
+impl<'a> CseBuilder<'a> {
+    // 自底向上重写，重复的子树第一次出现时生成绑定，之后直接引用
+    fn rewrite(&mut self, expr: &'a Expression) -> Expression {
+        if let Some(name) = self.names.get(expr) {
+            return Expression::variable(name);
+        }
+        let rewritten: Vec<Expression> = expr.children().into_iter().map(|child| self.rewrite(child)).collect();
+        let mut rewritten = rewritten.into_iter();
+        let rebuilt = expr.map_children(|_| rewritten.next().expect("map_children visits every child once"));
+
+        if self.counts.get(expr).copied().unwrap_or(0) < 2 {
+            return rebuilt;
+        }
+        let name = self.fresh.next();
+        self.names.insert(expr, name.clone());
+        self.bindings.push((name.clone(), rebuilt));
+        Expression::variable(&name)
+    }
+}
+
+// 重复子树里面的子树也会被计为重复，外层提出后它可能只剩一处引用，这里把它代回去
+fn inline_single_uses(mut expr: Expression, bindings: &mut Vec<(String, Expression)>) -> Expression {
+    for i in (0..bindings.len()).rev() {
+        let name = bindings[i].0.clone();
+        let mut uses = 0;
+        let mut count_uses = |e: &Expression| {
+            if matches!(e, Expression::Variable(v) if *v == name) {
+                uses += 1;
+            }
+        };
+        expr.walk(&mut count_uses);
+        for (_, later) in bindings[i + 1..].iter() {
+            later.walk(&mut count_uses);
+        }
+        if uses > 1 {
+            continue;
+        }
+
+        let (_, value) = bindings.remove(i);
+        expr = expr.substitute(&name, &value);
+        for (_, later) in bindings[i..].iter_mut() {
+            *later = later.substitute(&name, &value);
+        }
+    }
+    expr
+}
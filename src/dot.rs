@@ -0,0 +1,40 @@
+use crate::expression::{format_constant, Expression};
+use std::fmt::Write;
+
+impl Expression {
+    // 输出 Graphviz DOT 有向图，每个节点一个编号，边指向子表达式
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph expression {\n");
+        let mut next_id = 0;
+        self.write_dot_node(&mut out, &mut next_id);
+        out.push_str("}\n");
+        out
+    }
+
+    // 写出当前节点及其子树，返回当前节点的编号
+    fn write_dot_node(&self, out: &mut String, next_id: &mut usize) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+        let label = match self {
+            Expression::Constant(c) => format_constant(*c),
+            Expression::Variable(name) => name.clone(),
+            Expression::Add(_, _) => "+".to_string(),
+            Expression::Subtract(_, _) | Expression::Negate(_) => "-".to_string(),
+            Expression::Multiply(_, _) => "*".to_string(),
+            Expression::Divide(_, _) => "/".to_string(),
+            Expression::Power(_, _) => "^".to_string(),
+            Expression::Root(_, _) => "root".to_string(),
+            Expression::Log(_, _) => "log".to_string(),
+            Expression::Factorial(_) => "!".to_string(),
+            Expression::Polygamma(_, _) => "polygamma".to_string(),
+            _ => self.function_name().expect("remaining variants are named functions").to_string(),
+        };
+        writeln!(out, "    n{} [label=\"{}\"];", id, label.replace('"', "\\\"")).unwrap();
+
+        for child in self.children() {
+            let child_id = child.write_dot_node(out, next_id);
+            writeln!(out, "    n{} -> n{};", id, child_id).unwrap();
+        }
+        id
+    }
+}
@@ -0,0 +1,69 @@
+use crate::calculus::Differentiate;
+use crate::expression::Expression;
+use crate::parser::{ExpressionParser, ParseError};
+use crate::roots::SolveError;
+use std::fmt;
+use std::str::FromStr;
+
+// 方程 lhs = rhs
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Equation {
+    pub lhs: Expression,
+    pub rhs: Expression,
+}
+
+impl Equation {
+    pub fn new(lhs: Expression, rhs: Expression) -> Self {
+        Equation { lhs, rhs }
+    }
+
+    // 隐函数求导：两边同时对 var 求导，其余变量 v 都视为 var 的函数，
+    // 其导数记作变量 v'。例如 x^2 + y^2 = 1 对 x 求导得 2 * x + 2 * y * y' = 0
+    pub fn differentiate(&self, var: &str) -> Equation {
+        Equation::new(total_derivative(&self.lhs, var), total_derivative(&self.rhs, var))
+    }
+
+    // 移项得到 lhs - rhs = 0
+    pub fn move_all_to_lhs(&self) -> Equation {
+        Equation::new(
+            Expression::subtract(self.lhs.clone(), self.rhs.clone()),
+            Expression::constant(0.0),
+        )
+    }
+
+    // 解 lhs - rhs = 0，见 Expression::solve
+    pub fn solve(&self, var: &str) -> Result<Vec<Expression>, SolveError> {
+        self.move_all_to_lhs().lhs.solve(var)
+    }
+
+    pub fn simplify(&self) -> Equation {
+        Equation::new(self.lhs.simplify(), self.rhs.simplify())
+    }
+}
+
+// df/dvar + Σ df/dv * v'，v 取遍 var 以外的自由变量
+fn total_derivative(expr: &Expression, var: &str) -> Expression {
+    let chain_terms = expr
+        .variables()
+        .into_iter()
+        .filter(|v| v != var)
+        .map(|v| Expression::multiply(expr.differentiate(&v), Expression::variable(&format!("{}'", v))));
+    chain_terms
+        .fold(expr.differentiate(var), Expression::add)
+        .simplify()
+}
+
+impl fmt::Display for Equation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} = {}", self.lhs, self.rhs)
+    }
+}
+
+impl FromStr for Equation {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ExpressionParser::new(s).parse_equation()
+    }
+}
@@ -0,0 +1,378 @@
+use crate::expression::Expression;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    UnboundVariable(String),
+    DivisionByZero,
+    DomainError(String),
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::UnboundVariable(name) => write!(f, "Unbound variable: {}", name),
+            EvalError::DivisionByZero => write!(f, "Division by zero"),
+            EvalError::DomainError(msg) => write!(f, "Domain error: {}", msg),
+        }
+    }
+}
+
+impl Error for EvalError {}
+
+// 数值求值时三角函数参数和反三角函数结果的角度单位，只影响 evaluate_with_mode，
+// 求导、积分等符号运算始终按弧度：d/dx sin(x) = cos(x)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AngleMode {
+    #[default]
+    Radians,
+    Degrees,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+    Root,
+    Log,
+    Polygamma,
+    Min,
+    Max,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum UnaryOp {
+    Sin,
+    Cos,
+    Tan,
+    Sec,
+    Csc,
+    Cot,
+    Arcsin,
+    Arccos,
+    Arctan,
+    Sqrt,
+    Negate,
+    Exp,
+    Ln,
+    Sinh,
+    Cosh,
+    Tanh,
+    Arctanh,
+    Factorial,
+    Abs,
+    Heaviside,
+}
+
+impl BinaryOp {
+    pub(crate) fn apply(self, a: f64, b: f64) -> Result<f64, EvalError> {
+        match self {
+            BinaryOp::Add => Ok(a + b),
+            BinaryOp::Subtract => Ok(a - b),
+            BinaryOp::Multiply => Ok(a * b),
+            BinaryOp::Divide => {
+                if b == 0.0 {
+                    return Err(EvalError::DivisionByZero);
+                }
+                Ok(a / b)
+            }
+            BinaryOp::Power => {
+                if a == 0.0 && b < 0.0 {
+                    return Err(EvalError::DivisionByZero);
+                }
+                Ok(a.powf(b))
+            }
+            BinaryOp::Root => {
+                if b == 0.0 {
+                    return Err(EvalError::DivisionByZero);
+                }
+                // 奇数次根对负数取实根
+                if a < 0.0 && b.fract() == 0.0 && b % 2.0 != 0.0 {
+                    Ok(-(-a).powf(1.0 / b))
+                } else {
+                    Ok(a.powf(1.0 / b))
+                }
+            }
+            // a 为底数，b 为真数
+            BinaryOp::Log => {
+                if a <= 0.0 || a == 1.0 || b <= 0.0 {
+                    return Err(EvalError::DomainError(format!("log({}, {})", b, a)));
+                }
+                Ok(b.ln() / a.ln())
+            }
+            // a 为阶数，b 为自变量
+            BinaryOp::Polygamma => {
+                if a < 0.0 || a.fract() != 0.0 || (b <= 0.0 && b.fract() == 0.0) {
+                    return Err(EvalError::DomainError(format!("polygamma({}, {})", a, b)));
+                }
+                Ok(polygamma(a as u32, b))
+            }
+            BinaryOp::Min => Ok(a.min(b)),
+            BinaryOp::Max => Ok(a.max(b)),
+        }
+    }
+}
+
+impl UnaryOp {
+    // 角度模式下把三角函数的参数换成弧度，反三角函数的结果换回角度
+    pub(crate) fn apply_with_mode(self, x: f64, mode: AngleMode) -> Result<f64, EvalError> {
+        if mode == AngleMode::Radians {
+            return self.apply(x);
+        }
+        match self {
+            UnaryOp::Sin | UnaryOp::Cos | UnaryOp::Tan |
+            UnaryOp::Sec | UnaryOp::Csc | UnaryOp::Cot => self.apply(x.to_radians()),
+            UnaryOp::Arcsin | UnaryOp::Arccos | UnaryOp::Arctan => Ok(self.apply(x)?.to_degrees()),
+            _ => self.apply(x),
+        }
+    }
+
+    pub(crate) fn apply(self, x: f64) -> Result<f64, EvalError> {
+        match self {
+            UnaryOp::Sin => Ok(x.sin()),
+            UnaryOp::Cos => Ok(x.cos()),
+            UnaryOp::Tan => Ok(x.tan()),
+            UnaryOp::Sec => Ok(1.0 / x.cos()),
+            UnaryOp::Csc => {
+                if x.sin() == 0.0 {
+                    return Err(EvalError::DivisionByZero);
+                }
+                Ok(1.0 / x.sin())
+            }
+            UnaryOp::Cot => {
+                if x.sin() == 0.0 {
+                    return Err(EvalError::DivisionByZero);
+                }
+                Ok(x.cos() / x.sin())
+            }
+            UnaryOp::Arcsin => {
+                if !(-1.0..=1.0).contains(&x) {
+                    return Err(EvalError::DomainError(format!("arcsin({})", x)));
+                }
+                Ok(x.asin())
+            }
+            UnaryOp::Arccos => {
+                if !(-1.0..=1.0).contains(&x) {
+                    return Err(EvalError::DomainError(format!("arccos({})", x)));
+                }
+                Ok(x.acos())
+            }
+            UnaryOp::Arctan => Ok(x.atan()),
+            UnaryOp::Sqrt => {
+                if x < 0.0 {
+                    return Err(EvalError::DomainError(format!("sqrt({})", x)));
+                }
+                Ok(x.sqrt())
+            }
+            UnaryOp::Negate => Ok(-x),
+            UnaryOp::Exp => Ok(x.exp()),
+            UnaryOp::Ln => {
+                if x <= 0.0 {
+                    return Err(EvalError::DomainError(format!("ln({})", x)));
+                }
+                Ok(x.ln())
+            }
+            UnaryOp::Sinh => Ok(x.sinh()),
+            UnaryOp::Cosh => Ok(x.cosh()),
+            UnaryOp::Tanh => Ok(x.tanh()),
+            UnaryOp::Arctanh => {
+                if !(-1.0 < x && x < 1.0) {
+                    return Err(EvalError::DomainError(format!("arctanh({})", x)));
+                }
+                Ok(x.atanh())
+            }
+            UnaryOp::Factorial => {
+                if x < 0.0 && x.fract() == 0.0 {
+                    return Err(EvalError::DomainError(format!("{}!", x)));
+                }
+                if x.fract() == 0.0 && x <= 170.0 {
+                    return Ok((1..=x as u64).map(|k| k as f64).product());
+                }
+                Ok(gamma(x + 1.0))
+            }
+            UnaryOp::Abs => Ok(x.abs()),
+            UnaryOp::Heaviside => Ok(if x >= 0.0 { 1.0 } else { 0.0 }),
+
+        }
+    }
+}
+
+impl Expression {
+    pub fn evaluate(&self, bindings: &HashMap<String, f64>) -> Result<f64, EvalError> {
+        self.evaluate_with_mode(bindings, AngleMode::Radians)
+    }
+
+    // 与 evaluate 相同，三角函数按 mode 的角度单位求值：Degrees 下 sin(90) = 1、arctan(1) = 45
+    pub fn evaluate_with_mode(&self, bindings: &HashMap<String, f64>, mode: AngleMode) -> Result<f64, EvalError> {
+        let value = match self {
+            Expression::Constant(c) => *c,
+            Expression::Variable(name) => match bindings.get(name) {
+                Some(value) => *value,
+                None => return Err(EvalError::UnboundVariable(name.clone())),
+            },
+            // 只求值被选中的分支，另一分支的定义域错误不影响结果
+            Expression::Piecewise(condition, if_nonnegative, otherwise) => {
+                if condition.evaluate_with_mode(bindings, mode)? >= 0.0 {
+                    if_nonnegative.evaluate_with_mode(bindings, mode)?
+                } else {
+                    otherwise.evaluate_with_mode(bindings, mode)?
+                }
+            }
+            // lo > hi 时结果为 hi，与 min(max(x, lo), hi) 一致
+            Expression::Clamp(expr, lo, hi) => {
+                let x = expr.evaluate_with_mode(bindings, mode)?;
+                x.max(lo.evaluate_with_mode(bindings, mode)?).min(hi.evaluate_with_mode(bindings, mode)?)
+            }
+            _ => {
+                if let Some((op, left, right)) = self.binary_op() {
+                    op.apply(left.evaluate_with_mode(bindings, mode)?, right.evaluate_with_mode(bindings, mode)?)?
+                } else if let Some((op, expr)) = self.unary_op() {
+                    op.apply_with_mode(expr.evaluate_with_mode(bindings, mode)?, mode)?
+                } else {
+                    unreachable!("every composite node is a unary or binary operation")
+                }
+            }
+        };
+
+        if value.is_nan() {
+            return Err(EvalError::DomainError(self.to_string()));
+        }
+        Ok(value)
+    }
+
+    // 把所有参数都是常数的节点求值成一个常数，如 sin(0.5) * x => 0.479425538604203 * x；
+    // 求值出错或结果不是有限数（如 ln(0)、1/0）的节点保持原样
+    pub fn eval_constants(&self) -> Expression {
+        self.map(&|expr| {
+            let children = expr.children();
+            if children.is_empty() || !children.iter().all(|child| matches!(child, Expression::Constant(_))) {
+                return None;
+            }
+            match expr.evaluate(&HashMap::new()) {
+                Ok(value) if value.is_finite() => Some(Expression::constant(value)),
+                _ => None,
+            }
+        })
+    }
+
+    // 检查不含变量的部分是否有定义域错误，如 x/0、ln(0)、arcsin(2)。
+    // 含变量的参数无法判断，视为有定义；simplify 不折叠这些节点，原样保留
+    pub fn is_defined(&self) -> bool {
+        let constant_value = |expr: &Expression| match expr.variables().is_empty() {
+            true => Some(expr.evaluate(&HashMap::new())),
+            false => None,
+        };
+
+        let mut pending = vec![self];
+        while let Some(expr) = pending.pop() {
+            if let Some(value) = constant_value(expr) {
+                if value.is_err() {
+                    return false;
+                }
+                continue;
+            }
+            if let Expression::Divide(_, divisor) | Expression::Root(_, divisor) = expr {
+                if constant_value(divisor) == Some(Ok(0.0)) {
+                    return false;
+                }
+            }
+            pending.extend(expr.children());
+        }
+        true
+    }
+
+    pub(crate) fn binary_op(&self) -> Option<(BinaryOp, &Expression, &Expression)> {
+        let (op, left, right) = match self {
+            Expression::Add(left, right) => (BinaryOp::Add, left, right),
+            Expression::Subtract(left, right) => (BinaryOp::Subtract, left, right),
+            Expression::Multiply(left, right) => (BinaryOp::Multiply, left, right),
+            Expression::Divide(left, right) => (BinaryOp::Divide, left, right),
+            Expression::Power(base, exponent) => (BinaryOp::Power, base, exponent),
+            Expression::Root(base, n) => (BinaryOp::Root, base, n),
+            Expression::Log(base, expr) => (BinaryOp::Log, base, expr),
+            Expression::Polygamma(order, expr) => (BinaryOp::Polygamma, order, expr),
+            Expression::Min(left, right) => (BinaryOp::Min, left, right),
+            Expression::Max(left, right) => (BinaryOp::Max, left, right),
+            _ => return None,
+        };
+        Some((op, &**left, &**right))
+    }
+
+    pub(crate) fn unary_op(&self) -> Option<(UnaryOp, &Expression)> {
+        let (op, expr) = match self {
+            Expression::Sin(expr) => (UnaryOp::Sin, expr),
+            Expression::Cos(expr) => (UnaryOp::Cos, expr),
+            Expression::Tan(expr) => (UnaryOp::Tan, expr),
+            Expression::Sec(expr) => (UnaryOp::Sec, expr),
+            Expression::Csc(expr) => (UnaryOp::Csc, expr),
+            Expression::Cot(expr) => (UnaryOp::Cot, expr),
+            Expression::Arcsin(expr) => (UnaryOp::Arcsin, expr),
+            Expression::Arccos(expr) => (UnaryOp::Arccos, expr),
+            Expression::Arctan(expr) => (UnaryOp::Arctan, expr),
+            Expression::Sqrt(expr) => (UnaryOp::Sqrt, expr),
+            Expression::Negate(expr) => (UnaryOp::Negate, expr),
+            Expression::Exp(expr) => (UnaryOp::Exp, expr),
+            Expression::Ln(expr) => (UnaryOp::Ln, expr),
+            Expression::Sinh(expr) => (UnaryOp::Sinh, expr),
+            Expression::Cosh(expr) => (UnaryOp::Cosh, expr),
+            Expression::Tanh(expr) => (UnaryOp::Tanh, expr),
+            Expression::Arctanh(expr) => (UnaryOp::Arctanh, expr),
+            Expression::Factorial(expr) => (UnaryOp::Factorial, expr),
+            Expression::Abs(expr) => (UnaryOp::Abs, expr),
+            Expression::Heaviside(expr) => (UnaryOp::Heaviside, expr),
+            _ => return None,
+        };
+        Some((op, &**expr))
+    }
+}
+
+// Lanczos 近似（g = 7），x < 0.5 时用反射公式
+fn gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        return std::f64::consts::PI / ((std::f64::consts::PI * x).sin() * gamma(1.0 - x));
+    }
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let sum = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |acc, (i, c)| acc + c / (x + i as f64 + 1.0));
+    (2.0 * std::f64::consts::PI).sqrt() * t.powf(x + 0.5) * (-t).exp() * sum
+}
+
+// ψ⁽ⁿ⁾(x)：用 ψ⁽ⁿ⁾(x) = ψ⁽ⁿ⁾(x + 1) - (-1)ⁿ n! / x^(n+1) 把自变量推到 10 以上，
+// 再用渐近展开 (-1)^(n+1) [(n-1)!/xⁿ + n!/(2x^(n+1)) + Σ B₂ₖ (2k+n-1)! / ((2k)! x^(2k+n))]；n = 0 时首项换成 ln x
+fn polygamma(n: u32, mut x: f64) -> f64 {
+    const BERNOULLI: [f64; 4] = [1.0 / 6.0, -1.0 / 30.0, 1.0 / 42.0, -1.0 / 30.0];
+    let factorial = |k: u32| (1..=k).map(f64::from).product::<f64>();
+    let sign = if n.is_multiple_of(2) { -1.0 } else { 1.0 };
+
+    let mut result = 0.0;
+    while x < 10.0 {
+        result += sign * factorial(n) / x.powi(n as i32 + 1);
+        x += 1.0;
+    }
+
+    let leading = if n == 0 { x.ln() } else { sign * factorial(n - 1) / x.powi(n as i32) };
+    let mut series = factorial(n) / (2.0 * x.powi(n as i32 + 1));
+    for (k, b) in (1..).zip(BERNOULLI) {
+        series += b * factorial(2 * k + n - 1) / (factorial(2 * k) * x.powi((2 * k + n) as i32));
+    }
+    result + leading + sign * series
+}
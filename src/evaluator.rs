@@ -0,0 +1,196 @@
+use crate::expression::Expression;
+use crate::evaluate::{BinaryOp, EvalError, UnaryOp};
+
+#[derive(Debug, Clone, Copy)]
+enum Instruction {
+    Constant(f64),
+    Variable(usize),
+    Binary(BinaryOp),
+    Unary(UnaryOp),
+    // 弹出条件，为负时跳到目标下标
+    JumpIfNegative(usize),
+    Jump(usize),
+}
+
+// 预先把表达式树展平成后缀指令序列，变量按下标取值，
+// 适合在大量采样点上重复求值
+#[derive(Debug, Clone)]
+pub struct Evaluator {
+    variables: Vec<String>,
+    instructions: Vec<Instruction>,
+    stack_size: usize,
+}
+
+impl Evaluator {
+    // 变量的下标即其在 variables 中的位置
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    pub fn eval(&self, values: &[f64]) -> Result<f64, EvalError> {
+        if values.len() < self.variables.len() {
+            return Err(EvalError::UnboundVariable(self.variables[values.len()].clone()));
+        }
+
+        let mut stack = Vec::with_capacity(self.stack_size);
+        let mut next = 0;
+        while let Some(instruction) = self.instructions.get(next) {
+            next += 1;
+            let value = match *instruction {
+                Instruction::Constant(c) => c,
+                Instruction::Variable(index) => values[index],
+                Instruction::Binary(op) => {
+                    let b = stack.pop().unwrap();
+                    let a = stack.pop().unwrap();
+                    op.apply(a, b)?
+                }
+                Instruction::Unary(op) => {
+                    let x = stack.pop().unwrap();
+                    op.apply(x)?
+                }
+                Instruction::JumpIfNegative(target) => {
+                    if stack.pop().unwrap() < 0.0 {
+                        next = target;
+                    }
+                    continue;
+                }
+                Instruction::Jump(target) => {
+                    next = target;
+                    continue;
+                }
+            };
+            if value.is_nan() {
+                return Err(EvalError::DomainError(format!("{:?} produced NaN", instruction)));
+            }
+            stack.push(value);
+        }
+        Ok(stack.pop().unwrap())
+    }
+}
+
+impl Expression {
+    pub fn evaluator(&self) -> Evaluator {
+        self.evaluator_with(self.variables().into_iter().collect())
+    }
+
+    // 按 vars 的顺序取参数的闭包；求值出错（包括 vars 中缺少的自由变量）时返回 NaN
+    pub fn compile(&self, vars: &[&str]) -> impl Fn(&[f64]) -> f64 {
+        let mut variables: Vec<String> = vars.iter().map(|v| v.to_string()).collect();
+        for name in self.variables() {
+            if !variables.contains(&name) {
+                variables.push(name);
+            }
+        }
+
+        let evaluator = self.evaluator_with(variables);
+        let arity = vars.len();
+        move |values: &[f64]| evaluator.eval(&values[..arity.min(values.len())]).unwrap_or(f64::NAN)
+    }
+
+    // 在 xs 的每个点上求值，用于绘图；求值出错（定义域错误、除以零、还有其他自由变量）的点为 None
+    pub fn sample(&self, var: &str, xs: &[f64]) -> Vec<Option<f64>> {
+        let mut variables = vec![var.to_string()];
+        variables.extend(self.variables().into_iter().filter(|name| name != var));
+        let evaluator = self.evaluator_with(variables);
+        xs.iter().map(|&x| evaluator.eval(&[x]).ok()).collect()
+    }
+
+    fn evaluator_with(&self, variables: Vec<String>) -> Evaluator {
+        let mut instructions = Vec::new();
+        let stack_size = compile(self, &variables, &mut instructions);
+        Evaluator { variables, instructions, stack_size }
+    }
+}
+
+// 生成后缀指令，返回求值该子树所需的栈深度
+fn compile(expr: &Expression, variables: &[String], instructions: &mut Vec<Instruction>) -> usize {
+    match expr {
+        Expression::Constant(c) => {
+            instructions.push(Instruction::Constant(*c));
+            1
+        }
+        Expression::Variable(name) => {
+            let index = variables.iter().position(|v| v == name).unwrap();
+            instructions.push(Instruction::Variable(index));
+            1
+        }
+        // 条件 JumpIfNegative(otherwise) 分支一 Jump(end) 分支二，只执行被选中的分支
+        Expression::Piecewise(condition, if_nonnegative, otherwise) => {
+            let condition_depth = compile(condition, variables, instructions);
+            let branch = instructions.len();
+            instructions.push(Instruction::JumpIfNegative(0));
+            let first_depth = compile(if_nonnegative, variables, instructions);
+            let jump = instructions.len();
+            instructions.push(Instruction::Jump(0));
+            instructions[branch] = Instruction::JumpIfNegative(instructions.len());
+            let second_depth = compile(otherwise, variables, instructions);
+            instructions[jump] = Instruction::Jump(instructions.len());
+            condition_depth.max(first_depth).max(second_depth)
+        }
+        // 按 min(max(x, lo), hi) 生成指令
+        Expression::Clamp(inner, lo, hi) => {
+            let inner_depth = compile(inner, variables, instructions);
+            let lo_depth = compile(lo, variables, instructions);
+            instructions.push(Instruction::Binary(BinaryOp::Max));
+            let hi_depth = compile(hi, variables, instructions);
+            instructions.push(Instruction::Binary(BinaryOp::Min));
+            inner_depth.max(lo_depth + 1).max(hi_depth + 1)
+        }
+        _ => {
+            if let Some((op, left, right)) = expr.binary_op() {
+                let left_depth = compile(left, variables, instructions);
+                let right_depth = compile(right, variables, instructions);
+                instructions.push(Instruction::Binary(op));
+                left_depth.max(right_depth + 1)
+            } else if let Some((op, inner)) = expr.unary_op() {
+                let depth = compile(inner, variables, instructions);
+                instructions.push(Instruction::Unary(op));
+                depth
+            } else {
+                unreachable!("every composite node is a unary or binary operation")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse;
+    use crate::evaluate::EvalError;
+    use std::collections::HashMap;
+
+    #[test]
+    fn evaluator_matches_evaluate() {
+        for input in ["sin(x)^2 + x*cos(x*y)", "e^(-x^2) / (1 + y^2)", "abs(x - y)!", "clamp(x, -1, y)", "max(x, y) - min(x, y)"] {
+            let expr = parse(input).unwrap();
+            let evaluator = expr.evaluator();
+            assert_eq!(evaluator.variables(), ["x", "y"]);
+            for (x, y) in [(-2.0, 0.5), (0.0, 1.0), (0.7, 3.0), (4.0, -1.5)] {
+                let bindings = HashMap::from([("x".to_string(), x), ("y".to_string(), y)]);
+                assert_eq!(evaluator.eval(&[x, y]).ok(), expr.evaluate(&bindings).ok(), "{} at ({}, {})", input, x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn evaluator_reports_missing_values_and_domain_errors() {
+        let evaluator = parse("ln(x) + y").unwrap().evaluator();
+        assert_eq!(evaluator.eval(&[1.0]), Err(EvalError::UnboundVariable("y".to_string())));
+        assert!(evaluator.eval(&[-1.0, 0.0]).is_err());
+        assert_eq!(evaluator.eval(&[1.0, 2.0]), Ok(2.0));
+    }
+
+    #[test]
+    fn compiled_closure_samples_like_evaluate() {
+        let expr = parse("sin(x)^2 + x*cos(x*y)").unwrap();
+        let f = expr.compile(&["y", "x"]);
+        for i in 0..10_000 {
+            let x = i as f64 / 1_000.0;
+            let bindings = HashMap::from([("x".to_string(), x), ("y".to_string(), 0.5)]);
+            assert_eq!(f(&[0.5, x]), expr.evaluate(&bindings).unwrap());
+        }
+        // 出错的点和未列出的自由变量都得到 NaN
+        assert!(parse("ln(x)").unwrap().compile(&["x"])(&[-1.0]).is_nan());
+        assert!(parse("x + z").unwrap().compile(&["x"])(&[1.0]).is_nan());
+    }
+}
@@ -1,6 +1,12 @@
+use crate::factor::gcd;
+use std::collections::{BTreeSet, HashMap};
+use std::cmp::Ordering;
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem::discriminant;
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expression {
     Constant(f64),
     Variable(String),
@@ -10,11 +16,17 @@ pub enum Expression {
     Divide(Box<Expression>, Box<Expression>),
     Power(Box<Expression>, Box<Expression>),
     Root(Box<Expression>, Box<Expression>),
+    Sqrt(Box<Expression>),
+    // 取负 -u；simplify 保留 Negate，-1 * u 和 0 - u 也会化成这种形式
+    Negate(Box<Expression>),
     
     // 三角函数
     Sin(Box<Expression>),
     Cos(Box<Expression>),
     Tan(Box<Expression>),
+    Sec(Box<Expression>),
+    Csc(Box<Expression>),
+    Cot(Box<Expression>),
     
     // 反三角函数
     Arcsin(Box<Expression>),
@@ -24,14 +36,34 @@ pub enum Expression {
     // 指数和对数
     Exp(Box<Expression>),  // e^x
     Ln(Box<Expression>),   // 自然对数
-    Log(Box<Expression>, Box<Expression>), // 任意底数的对数，第二个参数是底数
+    Log(Box<Expression>, Box<Expression>), // 任意底数的对数，第一个参数是底数
     
     // 双曲函数
     Sinh(Box<Expression>),
     Cosh(Box<Expression>),
     Tanh(Box<Expression>),
+    Arctanh(Box<Expression>),
+
+    // 阶乘 n!，对非整数按 Γ(n + 1) 求值
+    Factorial(Box<Expression>),
+    // 多伽马函数 ψ⁽ⁿ⁾(x)，第一个参数是阶数 n；ψ⁽⁰⁾ 即 digamma，用于阶乘求导
+    Polygamma(Box<Expression>, Box<Expression>),
+
+    // 分段函数：Piecewise(c, a, b) 在 c >= 0 时取 a，否则取 b
+    Min(Box<Expression>, Box<Expression>),
+    Max(Box<Expression>, Box<Expression>),
+    Piecewise(Box<Expression>, Box<Expression>, Box<Expression>),
+
+    // 绝对值 |x|
+    Abs(Box<Expression>),
+
+    // 单位阶跃函数：x >= 0 时为 1，否则为 0
+    Heaviside(Box<Expression>),
+    // Clamp(x, lo, hi) 把 x 限制在 [lo, hi] 内，等价于 min(max(x, lo), hi)
+    Clamp(Box<Expression>, Box<Expression>, Box<Expression>),
 }
 
+#[allow(clippy::should_implement_trait)]
 impl Expression {
     pub fn constant(value: f64) -> Self {
         Expression::Constant(value)
@@ -61,10 +93,26 @@ impl Expression {
         Expression::Power(Box::new(base), Box::new(exponent))
     }
 
+    // 约分后的分数 n/d，分母为 1 时就是常数；分母为 0 时原样保留
+    pub fn rational(numerator: i64, denominator: i64) -> Self {
+        if denominator == 0 {
+            return Expression::divide(Expression::constant(numerator as f64), Expression::constant(0.0));
+        }
+        let divisor = gcd(numerator.unsigned_abs(), denominator.unsigned_abs()) as i64 * denominator.signum();
+        match (numerator / divisor, denominator / divisor) {
+            (n, 1) => Expression::constant(n as f64),
+            (n, d) => Expression::divide(Expression::constant(n as f64), Expression::constant(d as f64)),
+        }
+    }
+
     pub fn root(base: Expression, n: Expression) -> Self {
         Expression::Root(Box::new(base), Box::new(n))
     }
 
+    pub fn sqrt(expr: Expression) -> Expression {
+        Expression::Sqrt(Box::new(expr))
+    }
+
     pub fn ln(expr: Expression) -> Expression {
         Expression::Ln(Box::new(expr))
     }
@@ -81,6 +129,18 @@ impl Expression {
         Expression::Tan(Box::new(expr))
     }
 
+    pub fn sec(expr: Expression) -> Expression {
+        Expression::Sec(Box::new(expr))
+    }
+
+    pub fn csc(expr: Expression) -> Expression {
+        Expression::Csc(Box::new(expr))
+    }
+
+    pub fn cot(expr: Expression) -> Expression {
+        Expression::Cot(Box::new(expr))
+    }
+
     pub fn arcsin(expr: Expression) -> Expression {
         Expression::Arcsin(Box::new(expr))
     }
@@ -112,128 +172,853 @@ impl Expression {
     pub fn tanh(expr: Expression) -> Expression {
         Expression::Tanh(Box::new(expr))
     }
-}
 
-impl fmt::Display for Expression {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    pub fn arctanh(expr: Expression) -> Expression {
+        Expression::Arctanh(Box::new(expr))
+    }
+
+    pub fn negate(expr: Expression) -> Expression {
+        Expression::Negate(Box::new(expr))
+    }
+
+    pub fn abs(expr: Expression) -> Expression {
+        Expression::Abs(Box::new(expr))
+    }
+
+    pub fn heaviside(expr: Expression) -> Expression {
+        Expression::Heaviside(Box::new(expr))
+    }
+
+    pub fn clamp(expr: Expression, lo: Expression, hi: Expression) -> Expression {
+        Expression::Clamp(Box::new(expr), Box::new(lo), Box::new(hi))
+    }
+
+    pub fn factorial(expr: Expression) -> Expression {
+        Expression::Factorial(Box::new(expr))
+    }
+
+    pub fn digamma(expr: Expression) -> Expression {
+        Expression::polygamma(Expression::constant(0.0), expr)
+    }
+
+    pub fn polygamma(order: Expression, expr: Expression) -> Expression {
+        Expression::Polygamma(Box::new(order), Box::new(expr))
+    }
+
+    pub fn min(left: Expression, right: Expression) -> Expression {
+        Expression::Min(Box::new(left), Box::new(right))
+    }
+
+    pub fn max(left: Expression, right: Expression) -> Expression {
+        Expression::Max(Box::new(left), Box::new(right))
+    }
+
+    pub fn piecewise(condition: Expression, if_nonnegative: Expression, otherwise: Expression) -> Expression {
+        Expression::Piecewise(Box::new(condition), Box::new(if_nonnegative), Box::new(otherwise))
+    }
+
+    // 只识别字面常数节点，不对子树做常数折叠
+    pub fn constant_value(&self) -> Option<f64> {
         match self {
-            Expression::Constant(value) => {
-                if value.fract() == 0.0 {
-                    write!(f, "{}", *value as i64)
-                } else {
-                    write!(f, "{:.2}", value)
+            Expression::Constant(c) => Some(*c),
+            _ => None,
+        }
+    }
+
+    // 表达式中出现的所有自由变量，按名称排序
+    pub fn variables(&self) -> BTreeSet<String> {
+        let mut names = BTreeSet::new();
+        self.collect_variables(&mut names);
+        names
+    }
+
+    fn collect_variables(&self, names: &mut BTreeSet<String>) {
+        match self {
+            Expression::Variable(name) => {
+                names.insert(name.clone());
+            }
+            _ => {
+                for child in self.children() {
+                    child.collect_variables(names);
                 }
             }
-            Expression::Variable(name) => write!(f, "{}", name),
-            Expression::Add(left, right) => {
-                if let Expression::Constant(c) = **right {
-                    if c < 0.0 {
-                        write!(f, "{} - {}", left, -c)
-                    } else {
-                        write!(f, "{} + {}", left, right)
-                    }
+        }
+    }
+
+    // 每个变量作为叶子出现的次数，如 x*y + x => {x: 2, y: 1}
+    pub fn variable_counts(&self) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        self.walk(&mut |expr| {
+            if let Expression::Variable(name) = expr {
+                *counts.entry(name.clone()).or_insert(0) += 1;
+            }
+        });
+        counts
+    }
+
+    pub fn contains_variable(&self, var: &str) -> bool {
+        match self {
+            Expression::Variable(name) => name == var,
+            _ => self.children().iter().any(|child| child.contains_variable(var)),
+        }
+    }
+
+    // 结构相同且对应常数之差不超过 epsilon 时相等，如 0.1 + 0.2 与 0.3；
+    // 变量名和节点类型仍须完全一致，无穷大只与同号的无穷大相等
+    pub fn approx_eq(&self, other: &Expression, epsilon: f64) -> bool {
+        match (self, other) {
+            (Expression::Constant(a), Expression::Constant(b)) => a == b || (a - b).abs() <= epsilon,
+            (Expression::Variable(a), Expression::Variable(b)) => a == b,
+            _ => {
+                discriminant(self) == discriminant(other) &&
+                    self.children()
+                        .into_iter()
+                        .zip(other.children())
+                        .all(|(a, b)| a.approx_eq(b, epsilon))
+            }
+        }
+    }
+
+    // 忽略加法、乘法以及 min、max 交换顺序的结构比较：x + 2 * y 与 2 * y + x 等价。
+    // 这只是结构检查，不会证明数学上的等价（例如 2 * x 与 x + x 不等价）
+    pub fn equivalent(&self, other: &Expression) -> bool {
+        match (self, other) {
+            (Expression::Constant(a), Expression::Constant(b)) => a == b,
+            (Expression::Variable(a), Expression::Variable(b)) => a == b,
+            (Expression::Add(_, _), Expression::Add(_, _)) => {
+                same_operands(self.commutative_operands(), other.commutative_operands())
+            }
+            (Expression::Multiply(_, _), Expression::Multiply(_, _)) => {
+                same_operands(self.commutative_operands(), other.commutative_operands())
+            }
+            (Expression::Min(a1, b1), Expression::Min(a2, b2)) |
+            (Expression::Max(a1, b1), Expression::Max(a2, b2)) => {
+                (a1.equivalent(a2) && b1.equivalent(b2)) || (a1.equivalent(b2) && b1.equivalent(a2))
+            }
+            _ => {
+                discriminant(self) == discriminant(other) &&
+                    self.children()
+                        .into_iter()
+                        .zip(other.children())
+                        .all(|(a, b)| a.equivalent(b))
+            }
+        }
+    }
+
+    // 规范形式的字符串键：equivalent 的表达式键相同，可用作记忆化的 HashMap 键
+    pub fn structural_key(&self) -> String {
+        // -0.0 与 0.0 相等，键也要相同
+        let canonical = self.canonicalize().map(&|expr| match expr {
+            Expression::Constant(c) if *c == 0.0 => Some(Expression::constant(0.0)),
+            _ => None,
+        });
+        format!("{:?}", canonical)
+    }
+
+    // 与 equivalent 相同，积分时用它发现循环
+    pub fn structurally_similar(&self, other: &Expression) -> bool {
+        self.equivalent(other)
+    }
+
+    // 规范形式：递归展开嵌套的加法链、乘法链，并把操作数（以及 min、max 的两个参数）排成确定顺序
+    // （按 cmp_canonical：常数在前，其次按名称排列的变量，其余按节点类型和子表达式），再左结合重建。
+    // 等价（equivalent）的表达式规范化后结构相等
+    pub fn canonicalize(&self) -> Expression {
+        match self {
+            Expression::Add(_, _) | Expression::Multiply(_, _) => {
+                let mut operands: Vec<Expression> = self.commutative_operands()
+                    .into_iter()
+                    .map(|operand| operand.canonicalize())
+                    .collect();
+                operands.sort_by(|a, b| a.cmp_canonical(b));
+                let rebuild = if matches!(self, Expression::Add(_, _)) {
+                    Expression::add
+                } else {
+                    Expression::multiply
+                };
+                operands.into_iter().reduce(rebuild).expect("commutative chains have operands")
+            }
+            Expression::Min(left, right) | Expression::Max(left, right) => {
+                let mut operands = [left.canonicalize(), right.canonicalize()];
+                operands.sort_by(|a, b| a.cmp_canonical(b));
+                let [left, right] = operands;
+                if matches!(self, Expression::Min(_, _)) {
+                    Expression::min(left, right)
                 } else {
-                    write!(f, "{} + {}", left, right)
+                    Expression::max(left, right)
+                }
+            }
+            _ => self.map_children(|child| child.canonicalize()),
+        }
+    }
+
+    // 展开同一种可交换运算（加法或乘法）的嵌套链
+    fn commutative_operands(&self) -> Vec<&Expression> {
+        let mut operands = Vec::new();
+        let mut pending = vec![self];
+        while let Some(expr) = pending.pop() {
+            match expr {
+                Expression::Add(left, right) if matches!(self, Expression::Add(_, _)) => {
+                    pending.push(right);
+                    pending.push(left);
+                }
+                Expression::Multiply(left, right) if matches!(self, Expression::Multiply(_, _)) => {
+                    pending.push(right);
+                    pending.push(left);
                 }
+                _ => operands.push(expr),
+            }
+        }
+        operands
+    }
+
+    // 先序遍历：先访问节点本身，再从左到右访问子表达式
+    pub fn walk(&self, f: &mut dyn FnMut(&Expression)) {
+        let mut pending = vec![self];
+        while let Some(expr) = pending.pop() {
+            f(expr);
+            pending.extend(expr.children().into_iter().rev());
+        }
+    }
+
+    pub fn node_count(&self) -> usize {
+        let mut count = 0;
+        self.walk(&mut |_| count += 1);
+        count
+    }
+
+    // 叶子节点深度为 1
+    pub fn depth(&self) -> usize {
+        let mut deepest = 0;
+        let mut pending = vec![(self, 1)];
+        while let Some((expr, level)) = pending.pop() {
+            deepest = deepest.max(level);
+            pending.extend(expr.children().into_iter().map(|child| (child, level + 1)));
+        }
+        deepest
+    }
+
+    // 只统计运算符和函数节点，不含常数与变量
+    pub fn operation_count(&self) -> usize {
+        let mut count = 0;
+        self.walk(&mut |expr| {
+            if !expr.children().is_empty() {
+                count += 1;
+            }
+        });
+        count
+    }
+
+    // 自底向上改写：先改写子表达式，再把 f 用于重建后的节点；
+    // f 返回 None 时保留该节点
+    pub fn map(&self, f: &impl Fn(&Expression) -> Option<Expression>) -> Expression {
+        let rebuilt = self.map_children(|child| child.map(f));
+        f(&rebuilt).unwrap_or(rebuilt)
+    }
+
+    pub(crate) fn children(&self) -> Vec<&Expression> {
+        match self {
+            Expression::Constant(_) | Expression::Variable(_) => Vec::new(),
+            Expression::Add(left, right) |
+            Expression::Subtract(left, right) |
+            Expression::Multiply(left, right) |
+            Expression::Divide(left, right) |
+            Expression::Power(left, right) |
+            Expression::Root(left, right) |
+            Expression::Log(left, right) |
+            Expression::Polygamma(left, right) |
+            Expression::Min(left, right) |
+            Expression::Max(left, right) => vec![&**left, &**right],
+            Expression::Piecewise(condition, if_nonnegative, otherwise) => {
+                vec![&**condition, &**if_nonnegative, &**otherwise]
+            }
+            Expression::Clamp(expr, lo, hi) => vec![&**expr, &**lo, &**hi],
+            Expression::Sqrt(expr) |
+            Expression::Negate(expr) |
+            Expression::Sin(expr) |
+            Expression::Cos(expr) |
+            Expression::Tan(expr) |
+            Expression::Sec(expr) |
+            Expression::Csc(expr) |
+            Expression::Cot(expr) |
+            Expression::Arcsin(expr) |
+            Expression::Arccos(expr) |
+            Expression::Arctan(expr) |
+            Expression::Exp(expr) |
+            Expression::Ln(expr) |
+            Expression::Sinh(expr) |
+            Expression::Cosh(expr) |
+            Expression::Tanh(expr) |
+            Expression::Arctanh(expr) |
+            Expression::Factorial(expr) |
+            Expression::Abs(expr) |
+            Expression::Heaviside(expr) => vec![&**expr],
+        }
+    }
+
+    // 用 f 重建每个直接子节点，节点类型保持不变
+    pub(crate) fn map_children<F>(&self, mut f: F) -> Expression
+    where
+        F: FnMut(&Expression) -> Expression,
+    {
+        match self {
+            Expression::Constant(_) | Expression::Variable(_) => self.clone(),
+            Expression::Add(left, right) => Expression::add(f(left), f(right)),
+            Expression::Subtract(left, right) => Expression::subtract(f(left), f(right)),
+            Expression::Multiply(left, right) => Expression::multiply(f(left), f(right)),
+            Expression::Divide(left, right) => Expression::divide(f(left), f(right)),
+            Expression::Power(base, exponent) => Expression::power(f(base), f(exponent)),
+            Expression::Root(base, n) => Expression::root(f(base), f(n)),
+            Expression::Log(base, expr) => Expression::log(f(base), f(expr)),
+            Expression::Sin(expr) => Expression::sin(f(expr)),
+            Expression::Cos(expr) => Expression::cos(f(expr)),
+            Expression::Tan(expr) => Expression::tan(f(expr)),
+            Expression::Sec(expr) => Expression::sec(f(expr)),
+            Expression::Csc(expr) => Expression::csc(f(expr)),
+            Expression::Cot(expr) => Expression::cot(f(expr)),
+            Expression::Arcsin(expr) => Expression::arcsin(f(expr)),
+            Expression::Arccos(expr) => Expression::arccos(f(expr)),
+            Expression::Arctan(expr) => Expression::arctan(f(expr)),
+            Expression::Sqrt(expr) => Expression::sqrt(f(expr)),
+            Expression::Negate(expr) => Expression::negate(f(expr)),
+            Expression::Exp(expr) => Expression::exp(f(expr)),
+            Expression::Ln(expr) => Expression::ln(f(expr)),
+            Expression::Sinh(expr) => Expression::sinh(f(expr)),
+            Expression::Cosh(expr) => Expression::cosh(f(expr)),
+            Expression::Tanh(expr) => Expression::tanh(f(expr)),
+            Expression::Arctanh(expr) => Expression::arctanh(f(expr)),
+            Expression::Factorial(expr) => Expression::factorial(f(expr)),
+            Expression::Polygamma(order, expr) => Expression::polygamma(f(order), f(expr)),
+            Expression::Min(left, right) => Expression::min(f(left), f(right)),
+            Expression::Max(left, right) => Expression::max(f(left), f(right)),
+            Expression::Piecewise(condition, if_nonnegative, otherwise) => {
+                Expression::piecewise(f(condition), f(if_nonnegative), f(otherwise))
+            }
+            Expression::Abs(expr) => Expression::abs(f(expr)),
+            Expression::Heaviside(expr) => Expression::heaviside(f(expr)),
+            Expression::Clamp(expr, lo, hi) => Expression::clamp(f(expr), f(lo), f(hi)),
+        }
+    }
+}
+
+// 与 PartialEq 一致：相等的表达式哈希值相同。按先序遍历依次哈希每个节点，
+// 每种节点的子节点个数固定，所以这个序列能唯一确定树的结构
+impl Hash for Expression {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.walk(&mut |expr| {
+            discriminant(expr).hash(state);
+            match expr {
+                // 0.0 == -0.0，哈希前统一成 0.0
+                Expression::Constant(c) => (if *c == 0.0 { 0.0f64 } else { *c }).to_bits().hash(state),
+                Expression::Variable(name) => name.hash(state),
+                _ => {}
+            }
+        });
+    }
+}
+
+// 除了 NaN 常数不等于自身以外，PartialEq 满足等价关系
+impl Eq for Expression {}
+
+pub(crate) fn format_constant(value: f64) -> String {
+    DisplayOptions::default().format_constant(value)
+}
+
+// 乘号的写法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MultiplicationSymbol {
+    Asterisk,      // 2 * x
+    Times,         // 2 × x
+    Juxtaposition, // 2x、x y
+}
+
+// format_with 的输出选项，Default 与 Display 的输出一致
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayOptions {
+    pub multiplication: MultiplicationSymbol,
+    // 把 Exp(u) 写成 e^u 而不是 exp(u)
+    pub exp_as_power: bool,
+    // 非整数常数保留的小数位数，None 表示能原样解析回来的最短表示
+    pub precision: Option<usize>,
+    // 分母不超过 MAX_FRACTION_DENOMINATOR 的有理常数写成分数，如 0.5 => 1/2
+    pub fractions: bool,
+    // 省略 1 * u，并把 -1 * u 写成 -u
+    pub omit_unit_factors: bool,
+    // 数字系数紧跟变量、函数或括号时省略乘号：2x、3sin(x)、2(x + 1)，其余乘法照常输出
+    pub juxtapose_coefficients: bool,
+}
+
+impl Default for DisplayOptions {
+    fn default() -> Self {
+        DisplayOptions {
+            multiplication: MultiplicationSymbol::Asterisk,
+            exp_as_power: false,
+            precision: None,
+            fractions: false,
+            omit_unit_factors: true,
+            juxtapose_coefficients: false,
+        }
+    }
+}
+
+const MAX_FRACTION_DENOMINATOR: i64 = 1000;
+
+impl DisplayOptions {
+    fn format_constant(&self, value: f64) -> String {
+        if let Some((numerator, denominator)) = self.fraction(value) {
+            return format!("{}/{}", numerator, denominator);
+        }
+        match self.precision {
+            _ if value.fract() == 0.0 => format!("{}", value as i64),
+            Some(digits) => format!("{:.*}", digits, value),
+            None => format!("{}", value),
+        }
+    }
+
+    fn fraction(&self, value: f64) -> Option<(i64, i64)> {
+        if !self.fractions {
+            return None;
+        }
+        as_fraction(value)
+    }
+}
+
+// 非整数常数能否精确写成最简分数 n/d（按 f64 除法比较），分母不超过 MAX_FRACTION_DENOMINATOR
+pub(crate) fn as_fraction(value: f64) -> Option<(i64, i64)> {
+    if value.fract() == 0.0 || !value.is_finite() {
+        return None;
+    }
+    (2..=MAX_FRACTION_DENOMINATOR).find_map(|denominator| {
+        let numerator = (value * denominator as f64).round();
+        (numerator / denominator as f64 == value).then_some((numerator as i64, denominator))
+    })
+}
+
+impl Expression {
+    // 表达式之间的全序，用于把可交换的操作数排成确定的顺序：
+    // 先按节点类型（常数、变量，之后按枚举中的声明顺序），同类时常数按数值、变量按名称，
+    // 其余按子表达式从左到右逐个比较。与 PartialEq 一致：0.0 与 -0.0 视为相等
+    pub(crate) fn cmp_canonical(&self, other: &Expression) -> Ordering {
+        match (self, other) {
+            (Expression::Constant(x), Expression::Constant(y)) if x == y => Ordering::Equal,
+            (Expression::Constant(x), Expression::Constant(y)) => x.total_cmp(y),
+            (Expression::Variable(x), Expression::Variable(y)) => x.cmp(y),
+            _ => self.kind_rank().cmp(&other.kind_rank()).then_with(|| {
+                let (left, right) = (self.children(), other.children());
+                left.iter()
+                    .zip(&right)
+                    .map(|(a, b)| a.cmp_canonical(b))
+                    .find(|ordering| ordering.is_ne())
+                    .unwrap_or_else(|| left.len().cmp(&right.len()))
+            }),
+        }
+    }
+
+    fn kind_rank(&self) -> u8 {
+        match self {
+            Expression::Constant(_) => 0,
+            Expression::Variable(_) => 1,
+            Expression::Add(_, _) => 2,
+            Expression::Subtract(_, _) => 3,
+            Expression::Multiply(_, _) => 4,
+            Expression::Divide(_, _) => 5,
+            Expression::Power(_, _) => 6,
+            Expression::Root(_, _) => 7,
+            Expression::Sqrt(_) => 8,
+            Expression::Sin(_) => 9,
+            Expression::Cos(_) => 10,
+            Expression::Tan(_) => 11,
+            Expression::Sec(_) => 12,
+            Expression::Csc(_) => 13,
+            Expression::Cot(_) => 14,
+            Expression::Arcsin(_) => 15,
+            Expression::Arccos(_) => 16,
+            Expression::Arctan(_) => 17,
+            Expression::Exp(_) => 18,
+            Expression::Ln(_) => 19,
+            Expression::Log(_, _) => 20,
+            Expression::Sinh(_) => 21,
+            Expression::Cosh(_) => 22,
+            Expression::Tanh(_) => 23,
+            Expression::Arctanh(_) => 24,
+            Expression::Factorial(_) => 25,
+            Expression::Polygamma(_, _) => 26,
+            Expression::Min(_, _) => 27,
+            Expression::Max(_, _) => 28,
+            Expression::Piecewise(_, _, _) => 29,
+            Expression::Abs(_) => 30,
+            Expression::Negate(_) => 31,
+            Expression::Heaviside(_) => 32,
+            Expression::Clamp(_, _, _) => 33,
+        }
+    }
+}
+
+// 两组操作数能否一一配对；equivalent 是等价关系，贪心匹配即可
+fn same_operands(left: Vec<&Expression>, mut right: Vec<&Expression>) -> bool {
+    if left.len() != right.len() {
+        return false;
+    }
+    for operand in left {
+        match right.iter().position(|candidate| operand.equivalent(candidate)) {
+            Some(index) => {
+                right.swap_remove(index);
+            }
+            None => return false,
+        }
+    }
+    true
+}
+
+// Display 的输出片段：文本或尚待展开的子表达式
+enum Piece<'a> {
+    Text(&'a str),
+    Owned(String),
+    Expr(&'a Expression),
+}
+
+impl Expression {
+    pub(crate) fn function_name(&self) -> Option<&'static str> {
+        let name = match self {
+            Expression::Sin(_) => "sin",
+            Expression::Cos(_) => "cos",
+            Expression::Tan(_) => "tan",
+            Expression::Sec(_) => "sec",
+            Expression::Csc(_) => "csc",
+            Expression::Cot(_) => "cot",
+            Expression::Arcsin(_) => "arcsin",
+            Expression::Arccos(_) => "arccos",
+            Expression::Arctan(_) => "arctan",
+            Expression::Sqrt(_) => "sqrt",
+            Expression::Exp(_) => "exp",
+            Expression::Ln(_) => "ln",
+            Expression::Sinh(_) => "sinh",
+            Expression::Cosh(_) => "cosh",
+            Expression::Tanh(_) => "tanh",
+            Expression::Arctanh(_) => "arctanh",
+            Expression::Min(_, _) => "min",
+            Expression::Max(_, _) => "max",
+            Expression::Piecewise(_, _, _) => "piecewise",
+            Expression::Abs(_) => "abs",
+            Expression::Heaviside(_) => "heaviside",
+            Expression::Clamp(_, _, _) => "clamp",
+            _ => return None,
+        };
+        Some(name)
+    }
+
+    // 单个节点的输出片段，子表达式留给调用方继续展开
+    fn display_pieces<'a>(&'a self, opts: &DisplayOptions) -> Vec<Piece<'a>> {
+        fn operand(expr: &Expression, parens: bool) -> Vec<Piece<'_>> {
+            if parens {
+                vec![Piece::Text("("), Piece::Expr(expr), Piece::Text(")")]
+            } else {
+                vec![Piece::Expr(expr)]
             }
+        }
+
+        let is_sum = |expr: &Expression| matches!(expr, Expression::Add(_, _) | Expression::Subtract(_, _));
+        // 写成分数的常数与除法同级
+        let is_product = |expr: &Expression| match expr {
+            Expression::Multiply(_, _) | Expression::Divide(_, _) => true,
+            Expression::Constant(c) => opts.fraction(*c).is_some(),
+            _ => false,
+        };
+
+        match self {
+            Expression::Constant(value) => vec![Piece::Owned(opts.format_constant(*value))],
+            Expression::Variable(name) => vec![Piece::Text(name)],
+            Expression::Add(left, right) => match **right {
+                Expression::Constant(c) if c < 0.0 => {
+                    vec![Piece::Expr(left), Piece::Text(" - "), Piece::Owned(opts.format_constant(-c))]
+                }
+                _ => vec![Piece::Expr(left), Piece::Text(" + "), Piece::Expr(right)],
+            },
+            // 减法和除法左结合，右操作数同级时必须加括号：a - (b - c)、a / (b * c)
             Expression::Subtract(left, right) => {
-                write!(f, "{} - {}", left, right)
+                let mut pieces = vec![Piece::Expr(left), Piece::Text(" - ")];
+                pieces.extend(operand(right, is_sum(right)));
+                pieces
             }
             Expression::Multiply(left, right) => {
                 match (&**left, &**right) {
-                    (Expression::Constant(c), expr) | (expr, Expression::Constant(c)) if *c == -1.0 => {
-                        write!(f, "-{}", expr)
+                    (Expression::Constant(c), expr) | (expr, Expression::Constant(c))
+                        if *c == -1.0 && opts.omit_unit_factors =>
+                    {
+                        let mut pieces = vec![Piece::Text("-")];
+                        pieces.extend(operand(expr, is_sum(expr)));
+                        pieces
                     }
-                    (Expression::Constant(c), expr) | (expr, Expression::Constant(c)) if *c == 1.0 => {
-                        write!(f, "{}", expr)
+                    (Expression::Constant(c), expr) | (expr, Expression::Constant(c))
+                        if *c == 1.0 && opts.omit_unit_factors =>
+                    {
+                        vec![Piece::Expr(expr)]
                     }
                     _ => {
-                        let need_parens_left = matches!(**left, Expression::Add(_, _) | Expression::Subtract(_, _));
-                        let need_parens_right = matches!(**right, Expression::Add(_, _) | Expression::Subtract(_, _));
-                        
-                        if need_parens_left {
-                            write!(f, "({}) * ", left)?;
-                        } else {
-                            write!(f, "{} * ", left)?;
-                        }
-                        
-                        if need_parens_right {
-                            write!(f, "({})", right)
-                        } else {
-                            write!(f, "{}", right)
-                        }
+                        // 只有右操作数以字母或括号开头时系数才能紧贴，否则 2 * 3、2 * 2^x、2 * -x
+                        // 会写成 23、22^x、2-x；写成分数的系数也不行，1/2x 有歧义
+                        let coefficient = match **left {
+                            Expression::Constant(c) => c.is_finite() && opts.fraction(c).is_none(),
+                            _ => false,
+                        };
+                        let starts_with_name = |expr: &Expression| {
+                            matches!(expr,
+                                Expression::Variable(_) |
+                                Expression::Root(_, _) |
+                                Expression::Log(_, _) |
+                                Expression::Polygamma(_, _) |
+                                Expression::Exp(_)
+                            ) || expr.function_name().is_some() || is_sum(expr)
+                        };
+                        let juxtaposed = coefficient && match &**right {
+                            // 底数不加括号时是常数或其他乘方，都不能紧贴
+                            Expression::Power(base, _) => starts_with_name(base) || matches!(**base,
+                                Expression::Multiply(_, _) | Expression::Divide(_, _) | Expression::Negate(_)
+                            ),
+                            expr => starts_with_name(expr),
+                        };
+                        let symbol = match opts.multiplication {
+                            _ if juxtaposed && opts.juxtapose_coefficients => "",
+                            MultiplicationSymbol::Asterisk => " * ",
+                            MultiplicationSymbol::Times => " × ",
+                            // 系数能紧贴时省略分隔，其余用空格隔开；不能紧贴的数字仍用 *，避免 2 3 与 23 混淆
+                            MultiplicationSymbol::Juxtaposition if juxtaposed => "",
+                            MultiplicationSymbol::Juxtaposition if matches!(**left, Expression::Constant(_)) => " * ",
+                            MultiplicationSymbol::Juxtaposition => " ",
+                        };
+                        let mut pieces = operand(left, is_sum(left));
+                        pieces.push(Piece::Text(symbol));
+                        pieces.extend(operand(right, is_sum(right)));
+                        pieces
                     }
                 }
             }
+            Expression::Negate(expr) => {
+                let mut pieces = vec![Piece::Text("-")];
+                pieces.extend(operand(expr, is_sum(expr)));
+                pieces
+            }
             Expression::Divide(left, right) => {
-                let need_parens_left = matches!(**left, Expression::Add(_, _) | Expression::Subtract(_, _));
-                let need_parens_right = matches!(**right, Expression::Add(_, _) | Expression::Subtract(_, _));
-                
-                if need_parens_left {
-                    write!(f, "({}) / ", left)?;
-                } else {
-                    write!(f, "{} / ", left)?;
-                }
-                
-                if need_parens_right {
-                    write!(f, "({})", right)
-                } else {
-                    write!(f, "{}", right)
-                }
+                let mut pieces = operand(left, is_sum(left));
+                pieces.push(Piece::Text(" / "));
+                pieces.extend(operand(right, is_sum(right) || is_product(right)));
+                pieces
             }
             Expression::Power(base, exponent) => {
+                // 乘方右结合，底数是乘方或负常数时也要加括号
                 let need_parens = matches!(**base, 
                     Expression::Add(_, _) | 
                     Expression::Subtract(_, _) | 
                     Expression::Multiply(_, _) | 
-                    Expression::Divide(_, _)
-                );
-                
-                if need_parens {
-                    write!(f, "({})^{}", base, exponent)
-                } else {
-                    write!(f, "{}^{}", base, exponent)
-                }
-            }
-            Expression::Root(base, n) => {
-                write!(f, "√[{}]({})", n, base)
+                    Expression::Divide(_, _) |
+                    Expression::Power(_, _) |
+                    Expression::Negate(_)
+                ) || matches!(**base, Expression::Constant(c) if c < 0.0) || is_product(base);
+                let mut pieces = operand(base, need_parens);
+                pieces.push(Piece::Text("^"));
+                pieces.extend(operand(exponent, is_sum(exponent) || is_product(exponent)));
+                pieces
             }
-            Expression::Sin(expr) => {
-                write!(f, "sin({})", expr)
+            Expression::Exp(expr) if opts.exp_as_power => {
+                let mut pieces = vec![Piece::Text("e^")];
+                pieces.extend(operand(expr, is_sum(expr) || is_product(expr)));
+                pieces
             }
-            Expression::Cos(expr) => {
-                write!(f, "cos({})", expr)
+            // 后缀 !，只有变量、非负常数和函数调用不需要括号
+            Expression::Factorial(expr) => {
+                let atomic = match &**expr {
+                    Expression::Constant(c) => *c >= 0.0 && opts.fraction(*c).is_none(),
+                    Expression::Variable(_) => true,
+                    inner => inner.function_name().is_some(),
+                };
+                let mut pieces = operand(expr, !atomic);
+                pieces.push(Piece::Text("!"));
+                pieces
             }
-            Expression::Tan(expr) => {
-                write!(f, "tan({})", expr)
-            }
-            Expression::Arcsin(expr) => {
-                write!(f, "arcsin({})", expr)
-            }
-            Expression::Arccos(expr) => {
-                write!(f, "arccos({})", expr)
-            }
-            Expression::Arctan(expr) => {
-                write!(f, "arctan({})", expr)
-            }
-            Expression::Exp(expr) => {
-                write!(f, "exp({})", expr)
-            }
-            Expression::Ln(expr) => {
-                write!(f, "ln({})", expr)
+            // 与解析器的参数顺序一致：root(x, n)、log(x, b)
+            Expression::Root(base, n) => {
+                vec![Piece::Text("root("), Piece::Expr(base), Piece::Text(", "), Piece::Expr(n), Piece::Text(")")]
             }
+            Expression::Polygamma(order, expr) => match **order {
+                Expression::Constant(0.0) => vec![Piece::Text("digamma("), Piece::Expr(expr), Piece::Text(")")],
+                _ => vec![Piece::Text("polygamma("), Piece::Expr(order), Piece::Text(", "), Piece::Expr(expr), Piece::Text(")")],
+            },
             Expression::Log(base, expr) => {
-                write!(f, "log[{}]({})", base, expr)
-            }
-            Expression::Sinh(expr) => {
-                write!(f, "sinh({})", expr)
+                vec![Piece::Text("log("), Piece::Expr(expr), Piece::Text(", "), Piece::Expr(base), Piece::Text(")")]
             }
-            Expression::Cosh(expr) => {
-                write!(f, "cosh({})", expr)
+            _ => {
+                let name = self.function_name().expect("remaining variants are named functions");
+                let mut pieces = vec![Piece::Text(name), Piece::Text("(")];
+                for (i, child) in self.children().into_iter().enumerate() {
+                    if i > 0 {
+                        pieces.push(Piece::Text(", "));
+                    }
+                    pieces.push(Piece::Expr(child));
+                }
+                pieces.push(Piece::Text(")"));
+                pieces
             }
-            Expression::Tanh(expr) => {
-                write!(f, "tanh({})", expr)
+        }
+    }
+
+    pub fn format_with(&self, opts: &DisplayOptions) -> String {
+        let mut output = String::new();
+        self.write_with(&mut output, opts).expect("writing to a String cannot fail");
+        output
+    }
+
+    // 用显式工作栈代替递归，极深的表达式也不会栈溢出
+    fn write_with(&self, out: &mut dyn fmt::Write, opts: &DisplayOptions) -> fmt::Result {
+        let mut stack = vec![Piece::Expr(self)];
+        while let Some(piece) = stack.pop() {
+            match piece {
+                Piece::Text(text) => out.write_str(text)?,
+                Piece::Owned(text) => out.write_str(&text)?,
+                Piece::Expr(expr) => stack.extend(expr.display_pieces(opts).into_iter().rev()),
             }
         }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_with(f, &DisplayOptions::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn x() -> Expression {
+        Expression::variable("x")
+    }
+
+    fn y() -> Expression {
+        Expression::variable("y")
+    }
+
+    fn assert_round_trips(expr: Expression) {
+        let printed = expr.to_string();
+        assert_eq!(parse(&printed).unwrap(), expr, "{}", printed);
+    }
+
+    #[test]
+    fn right_operands_of_subtract_and_divide_keep_parens() {
+        let z = Expression::variable("z");
+        let nested_subtract = Expression::subtract(x(), Expression::subtract(y(), z.clone()));
+        assert_eq!(nested_subtract.to_string(), "x - (y - z)");
+        let nested_divide = Expression::divide(x(), Expression::divide(y(), z.clone()));
+        assert_eq!(nested_divide.to_string(), "x / (y / z)");
+        assert_eq!(Expression::divide(x(), Expression::multiply(y(), z.clone())).to_string(), "x / (y * z)");
+        assert_eq!(Expression::subtract(Expression::subtract(x(), y()), z).to_string(), "x - y - z");
+    }
+
+    #[test]
+    fn printed_form_parses_back_to_the_same_tree() {
+        let two = || Expression::constant(2.0);
+        for expr in [
+            Expression::subtract(x(), Expression::subtract(y(), two())),
+            Expression::subtract(x(), Expression::add(y(), two())),
+            Expression::divide(x(), Expression::divide(y(), two())),
+            Expression::divide(Expression::divide(x(), y()), two()),
+            Expression::divide(x(), Expression::multiply(y(), two())),
+            Expression::multiply(x(), Expression::add(y(), two())),
+            Expression::power(x(), Expression::power(y(), two())),
+            Expression::power(Expression::power(x(), y()), two()),
+            Expression::power(Expression::add(x(), y()), two()),
+            Expression::negate(Expression::add(x(), y())),
+            Expression::factorial(Expression::add(x(), two())),
+            Expression::log(two(), x()),
+            Expression::log(y(), Expression::add(x(), two())),
+            Expression::root(x(), Expression::constant(3.0)),
+            Expression::polygamma(two(), x()),
+            Expression::digamma(x()),
+            Expression::clamp(x(), y(), two()),
+            Expression::abs(Expression::subtract(x(), y())),
+        ] {
+            assert_round_trips(expr);
+        }
+    }
+
+    #[test]
+    fn log_and_root_print_as_function_calls() {
+        assert_eq!(Expression::log(Expression::constant(2.0), x()).to_string(), "log(x, 2)");
+        assert_eq!(Expression::root(x(), Expression::constant(3.0)).to_string(), "root(x, 3)");
+    }
+
+    #[test]
+    fn constant_value_only_sees_literal_constants() {
+        let sum = parse("2+3").unwrap();
+        assert_eq!(sum.constant_value(), None);
+        assert_eq!(sum.simplify().constant_value(), Some(5.0));
+        assert_eq!(parse("-2").unwrap().constant_value(), Some(-2.0));
+        assert_eq!(x().constant_value(), None);
+    }
+
+    #[test]
+    fn display_handles_very_deep_trees() {
+        let depth = 50_000;
+        let sum = (1..depth).fold(x(), |sum, _| Expression::add(sum, x()));
+        let nested = (0..depth).fold(x(), |inner, _| Expression::sin(inner));
+        let printed_sum = sum.to_string();
+        let printed_nested = nested.to_string();
+        // Drop 仍是递归的；先丢弃这两棵树，断言失败时才能正常展开
+        std::mem::forget(sum);
+        std::mem::forget(nested);
+
+        assert_eq!(printed_sum.len(), depth * 4 - 3);
+        assert!(printed_sum.starts_with("x + x + "));
+        assert_eq!(printed_nested.len(), depth * 5 + 1);
+        assert!(printed_nested.starts_with("sin(sin(") && printed_nested.contains("(x)"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip_preserves_the_tree() {
+        let expr = parse("sin(x)^2 + cos(x)^2").unwrap();
+        let json = serde_json::to_string(&expr).unwrap();
+        let back: Expression = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, expr);
+
+        let nested = parse("log(x, 2) - root(-x!, 3) / clamp(x, 0, 1)").unwrap();
+        let back: Expression = serde_json::from_str(&serde_json::to_string(&nested).unwrap()).unwrap();
+        assert_eq!(back, nested);
+    }
+
+    #[test]
+    fn walk_visits_nodes_in_pre_order() {
+        let expr = parse("x^2 + 3*x - 1").unwrap();
+        let mut count = 0;
+        let mut leaves = Vec::new();
+        expr.walk(&mut |node| {
+            count += 1;
+            if node.children().is_empty() {
+                leaves.push(node.to_string());
+            }
+        });
+        assert_eq!(count, 9);
+        assert_eq!(leaves, ["x", "2", "3", "x", "1"]);
+        assert_eq!(expr.node_count(), 9);
+        assert_eq!(expr.operation_count(), 4);
+    }
+
+    #[test]
+    fn map_rewrites_bottom_up() {
+        let expr = parse("x^2 + 3*x - 1").unwrap();
+        let scaled = expr.map(&|node| match node {
+            Expression::Constant(c) => Some(Expression::constant(c * 10.0)),
+            _ => None,
+        });
+        assert_eq!(scaled.to_string(), "x^20 + 30 * x - 10");
+
+        // f 看到的是已重建的子表达式：内层 sin 先变成 cos，外层也随之改写
+        let swapped = parse("sin(sin(x))").unwrap().map(&|node| match node {
+            Expression::Sin(inner) => Some(Expression::cos((**inner).clone())),
+            _ => None,
+        });
+        assert_eq!(swapped.to_string(), "cos(cos(x))");
     }
 }
@@ -0,0 +1,113 @@
+use crate::expression::Expression;
+use crate::simplify::{build_product, build_sum, scaled};
+
+// 拆开后的单项：数值系数，以及各个因子的底数和常数指数。
+// 不是常数幂的因子（变量、函数、括号里的和式等）记作指数 1
+struct Term {
+    coeff: f64,
+    powers: Vec<(Expression, f64)>,
+}
+
+impl Term {
+    fn new(expr: &Expression) -> Self {
+        let mut term = Term { coeff: 1.0, powers: Vec::new() };
+        for factor in expr.flatten_product() {
+            match factor {
+                Expression::Constant(c) => term.coeff *= c,
+                // -u 的系数取反，u 本身继续拆分因子
+                Expression::Negate(inner) => {
+                    let inner = Term::new(&inner);
+                    term.coeff *= -inner.coeff;
+                    for (base, exponent) in inner.powers {
+                        term.add_power(base, exponent);
+                    }
+                }
+                Expression::Power(base, exponent) => match *exponent {
+                    Expression::Constant(n) => term.add_power(*base, n),
+                    exponent => term.add_power(Expression::power(*base, exponent), 1.0),
+                },
+                factor => term.add_power(factor, 1.0),
+            }
+        }
+        term
+    }
+
+    fn add_power(&mut self, base: Expression, exponent: f64) {
+        match self.powers.iter_mut().find(|(b, _)| *b == base) {
+            Some((_, e)) => *e += exponent,
+            None => self.powers.push((base, exponent)),
+        }
+    }
+
+    fn power_of(&self, base: &Expression) -> Option<f64> {
+        self.powers.iter().find(|(b, _)| b == base).map(|(_, e)| *e)
+    }
+}
+
+fn base_power(base: &Expression, exponent: f64) -> Expression {
+    if exponent == 1.0 {
+        base.clone()
+    } else {
+        Expression::power(base.clone(), Expression::constant(exponent))
+    }
+}
+
+// 系数全为整数时取最大公约数，否则为 1
+fn common_coefficient(terms: &[Term]) -> f64 {
+    let integral = terms.iter().all(|t| t.coeff.fract() == 0.0 && t.coeff.abs() < 2f64.powi(53));
+    if !integral {
+        return 1.0;
+    }
+    let gcd = terms.iter().fold(0u64, |acc, t| gcd(acc, t.coeff.abs() as u64));
+    let sign = if terms.iter().all(|t| t.coeff < 0.0) { -1.0 } else { 1.0 };
+    if gcd == 0 { 1.0 } else { sign * gcd as f64 }
+}
+
+pub(crate) fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+impl Expression {
+    // 提取和式各项的公因子：数值系数的最大公约数，以及每一项都含有的因子的最低次幂，
+    // 因子可以是变量、函数或任意子表达式。
+    // 2*x + 4 => 2 * (x + 2)，x^2 + x => x * (x + 1)，x*sin(x) + x*cos(x) => x * (sin(x) + cos(x))；
+    // 没有公因子时原样返回
+    pub fn factor_common(&self) -> Expression {
+        let terms: Vec<Term> = self.flatten_sum().iter().map(Term::new).collect();
+        if terms.len() < 2 {
+            return self.clone();
+        }
+
+        let coeff = common_coefficient(&terms);
+        let common: Vec<(Expression, f64)> = terms[0].powers
+            .iter()
+            .filter_map(|(base, _)| {
+                let lowest = terms
+                    .iter()
+                    .map(|t| t.power_of(base))
+                    .try_fold(f64::INFINITY, |acc, e| e.map(|e| acc.min(e)))?;
+                (lowest > 0.0).then(|| (base.clone(), lowest))
+            })
+            .collect();
+        if coeff == 1.0 && common.is_empty() {
+            return self.clone();
+        }
+
+        let remaining: Vec<Expression> = terms
+            .into_iter()
+            .map(|term| {
+                let factors: Vec<Expression> = term.powers
+                    .iter()
+                    .filter_map(|(base, exponent)| {
+                        let lowest = common.iter().find(|(b, _)| b == base).map_or(0.0, |(_, e)| *e);
+                        let left = exponent - lowest;
+                        (left != 0.0).then(|| base_power(base, left))
+                    })
+                    .collect();
+                scaled(term.coeff / coeff, build_product(&factors))
+            })
+            .collect();
+        let factor: Vec<Expression> = common.iter().map(|(base, e)| base_power(base, *e)).collect();
+        Expression::multiply(scaled(coeff, build_product(&factor)), build_sum(&remaining))
+    }
+}
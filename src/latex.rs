@@ -0,0 +1,90 @@
+use crate::expression::{format_constant, Expression};
+
+// 运算优先级：加减 < 乘法/取负 < 乘方 < 原子（变量、函数、分式）
+fn precedence(expr: &Expression) -> u8 {
+    match expr {
+        Expression::Add(_, _) | Expression::Subtract(_, _) => 1,
+        Expression::Multiply(_, _) | Expression::Negate(_) => 2,
+        Expression::Constant(c) if *c < 0.0 => 2,
+        Expression::Power(_, _) | Expression::Exp(_) => 3,
+        _ => 4,
+    }
+}
+
+impl Expression {
+    pub fn to_latex(&self) -> String {
+        match self {
+            Expression::Constant(c) => format_constant(*c),
+            Expression::Variable(name) => name.clone(),
+            Expression::Add(left, right) => match **right {
+                Expression::Constant(c) if c < 0.0 => format!("{} - {}", left.to_latex(), format_constant(-c)),
+                _ => format!("{} + {}", left.to_latex(), right.to_latex()),
+            },
+            Expression::Subtract(left, right) => {
+                format!("{} - {}", left.to_latex(), latex_operand(right, 2))
+            }
+            Expression::Multiply(left, right) => match (&**left, &**right) {
+                (Expression::Constant(c), expr) | (expr, Expression::Constant(c)) if *c == -1.0 => {
+                    format!("-{}", latex_operand(expr, 2))
+                }
+                (Expression::Constant(c), expr) | (expr, Expression::Constant(c)) if *c == 1.0 => {
+                    expr.to_latex()
+                }
+                _ => format!("{} \\cdot {}", latex_operand(left, 2), latex_operand(right, 2)),
+            },
+            Expression::Divide(left, right) => {
+                format!("\\frac{{{}}}{{{}}}", left.to_latex(), right.to_latex())
+            }
+            Expression::Power(base, exponent) => {
+                format!("{}^{{{}}}", latex_operand(base, 4), exponent.to_latex())
+            }
+            Expression::Root(base, n) => match **n {
+                Expression::Constant(2.0) => format!("\\sqrt{{{}}}", base.to_latex()),
+                _ => format!("\\sqrt[{}]{{{}}}", n.to_latex(), base.to_latex()),
+            },
+            Expression::Sqrt(expr) => format!("\\sqrt{{{}}}", expr.to_latex()),
+            Expression::Negate(expr) => format!("-{}", latex_operand(expr, 2)),
+            Expression::Exp(expr) => format!("e^{{{}}}", expr.to_latex()),
+            // LaTeX 没有内置 \arctanh
+            Expression::Arctanh(expr) => {
+                format!("\\operatorname{{arctanh}}\\left({}\\right)", expr.to_latex())
+            }
+            Expression::Factorial(expr) => format!("{}!", latex_operand(expr, 4)),
+            Expression::Abs(expr) => format!("\\left|{}\\right|", expr.to_latex()),
+            Expression::Heaviside(expr) => format!("H\\left({}\\right)", expr.to_latex()),
+            Expression::Clamp(expr, lo, hi) => format!(
+                "\\operatorname{{clamp}}\\left({}, {}, {}\\right)",
+                expr.to_latex(),
+                lo.to_latex(),
+                hi.to_latex()
+            ),
+            Expression::Polygamma(order, expr) => match **order {
+                Expression::Constant(0.0) => format!("\\psi\\left({}\\right)", expr.to_latex()),
+                _ => format!("\\psi^{{({})}}\\left({}\\right)", order.to_latex(), expr.to_latex()),
+            },
+            Expression::Log(base, expr) => {
+                format!("\\log_{{{}}}\\left({}\\right)", base.to_latex(), expr.to_latex())
+            }
+            Expression::Piecewise(condition, if_nonnegative, otherwise) => format!(
+                "\\begin{{cases}} {} & {} \\geq 0 \\\\ {} & \\text{{otherwise}} \\end{{cases}}",
+                if_nonnegative.to_latex(),
+                condition.to_latex(),
+                otherwise.to_latex()
+            ),
+            _ => {
+                let name = self.function_name().expect("remaining variants are named functions");
+                let args: Vec<String> = self.children().iter().map(|child| child.to_latex()).collect();
+                format!("\\{}\\left({}\\right)", name, args.join(", "))
+            }
+        }
+    }
+}
+
+// 子表达式优先级低于 min_precedence 时加括号
+fn latex_operand(expr: &Expression, min_precedence: u8) -> String {
+    if precedence(expr) < min_precedence {
+        format!("\\left({}\\right)", expr.to_latex())
+    } else {
+        expr.to_latex()
+    }
+}
@@ -1,8 +1,28 @@
 pub mod expression;
+pub mod equation;
 pub mod parser;
 pub mod calculus;
 pub mod simplify;
+pub mod polynomial;
+pub mod substitute;
+pub mod latex;
+pub mod dot;
+pub mod tree;
+pub mod ops;
+pub mod factor;
+pub mod cse;
+pub mod roots;
+pub mod evaluate;
+pub mod evaluator;
+pub mod analysis;
+#[cfg(feature = "num-complex")]
+pub mod complex;
 
 // Re-export commonly used items
-pub use expression::Expression;
-pub use parser::ExpressionParser;
+pub use equation::Equation;
+pub use expression::{DisplayOptions, Expression, MultiplicationSymbol};
+pub use parser::{parse, ExpressionParser, ParseError};
+pub use simplify::SimplifyOptions;
+pub use evaluate::{AngleMode, EvalError};
+pub use evaluator::Evaluator;
+pub use analysis::{analyze, AnalysisResult};
@@ -1,23 +1,19 @@
-use wavesurf::ExpressionParser;
-use wavesurf::calculus::Differentiate;
+use wavesurf::analyze;
 
 fn test_expression(expr: &str) {
     println!("\nTesting expression: {}", expr);
     
-    let mut parser = ExpressionParser::new(expr);
-    match parser.parse() {
-        Ok(expr) => {
-            println!("Parsed: {}", expr);
-            let simplified = expr.simplify();
-            println!("Simplified: {}", simplified);
-            let derivative = simplified.differentiate("x").simplify();
-            println!("Derivative: {}", derivative);
-            match simplified.integrate("x") {
-                Ok(integral) => println!("Integral: {}", integral.simplify()),
+    match analyze(expr, "x") {
+        Ok(result) => {
+            println!("Parsed: {}", result.parsed);
+            println!("Simplified: {}", result.simplified);
+            println!("Derivative: {}", result.derivative);
+            match result.integral {
+                Ok(integral) => println!("Integral: {}", integral),
                 Err(e) => println!("Integration error: {}", e),
             }
         }
-        Err(e) => println!("Parse error: {}", e),
+        Err(e) => println!("Parse error:\n{}", e.render(expr)),
     }
 }
 
@@ -42,11 +38,14 @@ fn main() {
         "e^x",
         "ln(x)",
         "e^(2*x)",
+        "x^x",
         
         // 复合函数
         "sin(x^2)",
         "e^(sin(x))",
         "ln(cos(x))",
+        "2*x*cos(x^2)",
+        "x*e^(x^2)",
         
         // 分数和有理函数
         "(x^2 + 1)/(x - 1)",
@@ -0,0 +1,79 @@
+use crate::expression::Expression;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+// 为值和引用的四种组合实现二元运算符，只构造节点，不做化简
+macro_rules! impl_binary_op {
+    ($trait:ident, $method:ident, $constructor:ident) => {
+        impl $trait<Expression> for Expression {
+            type Output = Expression;
+            fn $method(self, rhs: Expression) -> Expression {
+                Expression::$constructor(self, rhs)
+            }
+        }
+
+        impl $trait<&Expression> for Expression {
+            type Output = Expression;
+            fn $method(self, rhs: &Expression) -> Expression {
+                Expression::$constructor(self, rhs.clone())
+            }
+        }
+
+        impl $trait<Expression> for &Expression {
+            type Output = Expression;
+            fn $method(self, rhs: Expression) -> Expression {
+                Expression::$constructor(self.clone(), rhs)
+            }
+        }
+
+        impl $trait<&Expression> for &Expression {
+            type Output = Expression;
+            fn $method(self, rhs: &Expression) -> Expression {
+                Expression::$constructor(self.clone(), rhs.clone())
+            }
+        }
+    };
+}
+
+impl_binary_op!(Add, add, add);
+impl_binary_op!(Sub, sub, subtract);
+impl_binary_op!(Mul, mul, multiply);
+impl_binary_op!(Div, div, divide);
+
+// 取负与解析器一致：-x 表示为 Negate(x)
+impl Neg for Expression {
+    type Output = Expression;
+    fn neg(self) -> Expression {
+        Expression::negate(self)
+    }
+}
+
+impl Neg for &Expression {
+    type Output = Expression;
+    fn neg(self) -> Expression {
+        -self.clone()
+    }
+}
+
+impl From<f64> for Expression {
+    fn from(value: f64) -> Self {
+        Expression::constant(value)
+    }
+}
+
+impl From<&str> for Expression {
+    fn from(name: &str) -> Self {
+        Expression::variable(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Expression;
+
+    #[test]
+    fn neg_builds_negate() {
+        let x = Expression::variable("x");
+        assert_eq!(-x.clone(), Expression::negate(x.clone()));
+        assert_eq!(-&x, Expression::negate(x));
+    }
+}
@@ -1,42 +1,370 @@
+use crate::equation::Equation;
 use crate::expression::Expression;
+use std::error::Error;
+use std::fmt;
 use std::iter::Peekable;
-use std::str::Chars;
+use std::str::{CharIndices, FromStr};
+
+// 解析错误，position 为出错处的字符下标（从 0 开始）
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    UnexpectedChar { found: char, position: usize },
+    UnexpectedEof { position: usize },
+    // 未闭合的 '(' 或多余的 ')'，position 指向该括号
+    UnbalancedParen { position: usize },
+    InvalidNumber { text: String, position: usize },
+    TrailingInput { found: char, position: usize },
+    // 函数参数个数不对，position 指向函数名的开头
+    WrongArity { name: String, expected: usize, found: usize, position: usize },
+    // 未闭合的 '|' 或多余的 '|'，position 指向该竖线
+    UnmatchedBar { position: usize },
+    // 函数名后面缺少 '('，position 指向函数名之后的词法单元（输入已结束时为输入长度）
+    MissingFunctionParen { name: String, position: usize },
+    // 嵌套层数超过 ExpressionParser::with_max_depth 设定的上限，position 指向超出上限的那个词法单元
+    TooDeep { limit: usize, position: usize },
+    // 一条运算链（a + b + ...、a * b * ...）中的运算符超过 ExpressionParser::with_max_chain 设定的上限，
+    // position 指向超出上限的那个运算符
+    ChainTooLong { limit: usize, position: usize },
+    // ^ 或 ** 之后缺少指数，found 为该处的字符（输入已结束时为 None）
+    MissingExponent { found: Option<char>, position: usize },
+}
+
+impl ParseError {
+    pub fn position(&self) -> usize {
+        match self {
+            ParseError::UnexpectedChar { position, .. } |
+            ParseError::UnexpectedEof { position } |
+            ParseError::UnbalancedParen { position } |
+            ParseError::InvalidNumber { position, .. } |
+            ParseError::TrailingInput { position, .. } |
+            ParseError::WrongArity { position, .. } |
+            ParseError::MissingFunctionParen { position, .. } |
+            ParseError::UnmatchedBar { position } |
+            ParseError::TooDeep { position, .. } |
+            ParseError::ChainTooLong { position, .. } |
+            ParseError::MissingExponent { position, .. } => *position,
+        }
+    }
+
+    // 出错的字符，输入意外结束时为 None
+    pub fn found(&self) -> Option<char> {
+        match self {
+            ParseError::UnexpectedChar { found, .. } | ParseError::TrailingInput { found, .. } => Some(*found),
+            ParseError::MissingExponent { found, .. } => *found,
+            ParseError::InvalidNumber { text, .. } => text.chars().next(),
+            ParseError::UnbalancedParen { .. }
+            | ParseError::UnexpectedEof { .. }
+            | ParseError::WrongArity { .. }
+            | ParseError::MissingFunctionParen { .. }
+            | ParseError::UnmatchedBar { .. }
+            | ParseError::TooDeep { .. }
+            | ParseError::ChainTooLong { .. } => None,
+        }
+    }
+
+    // 在原输入下方用 ^ 标出出错位置，例如
+    //   x + )
+    //       ^ Unexpected character ')' at position 4
+    pub fn render(&self, input: &str) -> String {
+        format!("{}\n{}^ {}", input, " ".repeat(self.position()), self)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnexpectedChar { found, position } => {
+                write!(f, "Unexpected character '{}' at position {}", found, position)
+            }
+            ParseError::UnexpectedEof { position } => write!(f, "Unexpected end of input at position {}", position),
+            ParseError::UnbalancedParen { position } => write!(f, "Unbalanced parenthesis at position {}", position),
+            ParseError::InvalidNumber { text, position } => {
+                write!(f, "Invalid number '{}' at position {}", text, position)
+            }
+            ParseError::TrailingInput { found, position } => {
+                write!(f, "Unexpected trailing input '{}' at position {}", found, position)
+            }
+            ParseError::WrongArity { name, expected, found, position } => {
+                write!(f, "Function '{}' expects {} argument(s) but got {} at position {}", name, expected, found, position)
+            }
+            ParseError::UnmatchedBar { position } => write!(f, "Unmatched '|' at position {}", position),
+            ParseError::MissingFunctionParen { name, position } => {
+                write!(f, "Expected '(' after function '{}' at position {}", name, position)
+            }
+            ParseError::TooDeep { limit, position } => {
+                write!(f, "Expression nested deeper than {} levels at position {}", limit, position)
+            }
+            ParseError::ChainTooLong { limit, position } => {
+                write!(f, "Operator chain longer than {} operators at position {}", limit, position)
+            }
+            ParseError::MissingExponent { found: Some(found), position } => {
+                write!(f, "Expected exponent after '^', found '{}' at position {}", found, position)
+            }
+            ParseError::MissingExponent { found: None, position } => {
+                write!(f, "Expected exponent after '^', found end of input at position {}", position)
+            }
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+// 等同于 ExpressionParser::new(input).parse()
+pub fn parse(input: &str) -> Result<Expression, ParseError> {
+    ExpressionParser::new(input).parse()
+}
+
+impl FromStr for Expression {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s)
+    }
+}
+
+// 词法单元；数字和标识符保留原文，数字的合法性留给语法分析检查
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum TokenKind<'a> {
+    Number(&'a str),
+    Identifier(&'a str),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Caret,
+    // ** 是 ^ 的另一种写法（Python、Fortran 的习惯）
+    DoubleStar,
+    Bang,
+    // 绝对值的竖线，开和闭是同一个符号，由语法分析按位置区分
+    Bar,
+    LParen,
+    RParen,
+    Comma,
+    Equals,
+    // 无法识别的字符，语法分析读到它时才报错，以保证错误按从左到右的顺序报告
+    Unknown(char),
+}
+
+// position 为词法单元第一个字符的下标（按字符计，从 0 开始）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Token<'a> {
+    pub(crate) kind: TokenKind<'a>,
+    pub(crate) position: usize,
+}
+
+impl Token<'_> {
+    // 词法单元的第一个字符，用于错误信息
+    fn first_char(&self) -> char {
+        match self.kind {
+            TokenKind::Number(text) | TokenKind::Identifier(text) => text.chars().next().unwrap(),
+            TokenKind::Plus => '+',
+            TokenKind::Minus => '-',
+            TokenKind::Star => '*',
+            TokenKind::Slash => '/',
+            TokenKind::Caret => '^',
+            TokenKind::DoubleStar => '*',
+            TokenKind::Bang => '!',
+            TokenKind::Bar => '|',
+            TokenKind::LParen => '(',
+            TokenKind::RParen => ')',
+            TokenKind::Comma => ',',
+            TokenKind::Equals => '=',
+            TokenKind::Unknown(c) => c,
+        }
+    }
+
+    fn unexpected(&self) -> ParseError {
+        ParseError::UnexpectedChar { found: self.first_char(), position: self.position }
+    }
+}
+
+// 把输入切分成词法单元并跳过空白，本身不会出错
+pub(crate) struct Lexer<'a> {
+    source: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    position: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub(crate) fn new(source: &'a str) -> Self {
+        Lexer { source, chars: source.char_indices().peekable(), position: 0 }
+    }
+
+    // 返回词法单元序列，以及输入结束处的下标
+    pub(crate) fn tokenize(mut self) -> (Vec<Token<'a>>, usize) {
+        let mut tokens = Vec::new();
+        while let Some(&(offset, c)) = self.chars.peek() {
+            let position = self.position;
+            if c.is_whitespace() {
+                self.advance();
+                continue;
+            }
+            let kind = match c {
+                '0'..='9' => TokenKind::Number(self.take_while(offset, |c| c.is_ascii_digit() || c == '.')),
+                // 除 ASCII 字母外也接受 α、θ 等字母开头的名字
+                c if c.is_alphabetic() => TokenKind::Identifier(self.take_while(offset, char::is_alphanumeric)),
+                _ => {
+                    self.advance();
+                    match c {
+                        '+' => TokenKind::Plus,
+                        '-' => TokenKind::Minus,
+                        '*' if self.chars.peek().map(|&(_, next)| next) == Some('*') => {
+                            self.advance();
+                            TokenKind::DoubleStar
+                        }
+                        '*' => TokenKind::Star,
+                        '/' => TokenKind::Slash,
+                        '^' => TokenKind::Caret,
+                        '!' => TokenKind::Bang,
+                        '|' => TokenKind::Bar,
+                        '(' => TokenKind::LParen,
+                        ')' => TokenKind::RParen,
+                        ',' => TokenKind::Comma,
+                        '=' => TokenKind::Equals,
+                        _ => TokenKind::Unknown(c),
+                    }
+                }
+            };
+            tokens.push(Token { kind, position });
+        }
+        (tokens, self.position)
+    }
+
+    // 消耗从字节偏移 start 开始、满足 accept 的最长一段字符
+    fn take_while(&mut self, start: usize, accept: impl Fn(char) -> bool) -> &'a str {
+        let mut end = start;
+        while let Some(&(offset, c)) = self.chars.peek() {
+            if !accept(c) {
+                break;
+            }
+            end = offset + c.len_utf8();
+            self.advance();
+        }
+        &self.source[start..end]
+    }
+
+    fn advance(&mut self) {
+        if self.chars.next().is_some() {
+            self.position += 1;
+        }
+    }
+}
+
+// 默认的嵌套上限。括号、一元负号、函数参数、指数和后缀 ! 都计一层。实测在 2MB 的线程栈、
+// 未优化构建下，嵌套这么深的树 simplify、differentiate 后再 simplify 都不会溢出；
+// 求导得到的树更深，所以上限比 simplify 自身能处理的 100 层左右低
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+// 默认的运算链长度上限。a + b + c、x * y / z 这样的左结合运算链不计入嵌套层数：
+// simplify 和 differentiate 沿链的左操作数迭代，链的长度不会加深它们的递归。
+// 但 Clone、== 仍按树的深度递归，乘积链求导后深度还会翻倍；实测在 2MB 的线程栈、未优化构建下，
+// 这个长度的链 simplify、differentiate 都不会溢出，和式与乘积的链求导后再 simplify 也不会
+// （商的链求导后体积是平方级的，化简很慢，测试里没有做）
+pub const DEFAULT_MAX_CHAIN: usize = 1_000;
 
 pub struct ExpressionParser<'a> {
-    input: Peekable<Chars<'a>>,
+    tokens: Vec<Token<'a>>,
+    next: usize,
+    // 输入结束处的下标，用于 UnexpectedEof
+    end: usize,
+    depth: usize,
+    max_depth: usize,
+    max_chain: usize,
+    // 为 true 时单参数函数可以不带括号作用于紧随其后的基本表达式：sin x = sin(x)
+    implicit_application: bool,
+    // 为 true 时 alpha、theta 等希腊字母名解析为单个字符的变量 α、θ
+    greek_letters: bool,
 }
 
 impl<'a> ExpressionParser<'a> {
     pub fn new(input: &'a str) -> Self {
+        let (tokens, end) = Lexer::new(input).tokenize();
         ExpressionParser {
-            input: input.chars().peekable(),
+            tokens,
+            next: 0,
+            end,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_chain: DEFAULT_MAX_CHAIN,
+            implicit_application: false,
+            greek_letters: false,
         }
     }
 
-    pub fn parse(&mut self) -> Result<Expression, String> {
-        self.parse_expression()
+    // 嵌套超过 max_depth 层时返回 ParseError::TooDeep 而不是继续递归
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    // 一条运算链中的运算符超过 max_chain 个时返回 ParseError::ChainTooLong
+    pub fn with_max_chain(mut self, max_chain: usize) -> Self {
+        self.max_chain = max_chain;
+        self
+    }
+
+    // 允许 sin x、ln 2 这样省略括号的写法。参数只取一个基本表达式（数字、变量、括号或函数调用），
+    // 所以 sin x^2 = sin(x)^2，sin 2*x = sin(2)*x；多参数函数仍然必须带括号
+    pub fn with_implicit_application(mut self, enabled: bool) -> Self {
+        self.implicit_application = enabled;
+        self
     }
 
-    fn parse_expression(&mut self) -> Result<Expression, String> {
+    // 把小写的希腊字母名 alpha、beta、theta 等解析为变量 α、β、θ，大小写不同的名字（Alpha）不转换。
+    // pi 不在其中，仍是名为 pi 的变量
+    pub fn with_greek_letters(mut self, enabled: bool) -> Self {
+        self.greek_letters = enabled;
+        self
+    }
+
+    // 整个输入必须是一个表达式，剩余的词法单元视为错误
+    pub fn parse(&mut self) -> Result<Expression, ParseError> {
+        let expr = self.parse_expression()?;
+        self.expect_end()?;
+        Ok(expr)
+    }
+
+    // 整个输入必须是恰好含一个顶层 = 的方程，如 y = x^2 + 1
+    pub fn parse_equation(&mut self) -> Result<Equation, ParseError> {
+        let lhs = self.parse_expression()?;
+        match self.peek() {
+            Some(token) if token.kind == TokenKind::Equals => self.advance(),
+            Some(token) => return Err(token.unexpected()),
+            None => return Err(self.unexpected_eof()),
+        }
+        let rhs = self.parse_expression()?;
+        self.expect_end()?;
+        Ok(Equation::new(lhs, rhs))
+    }
+
+    fn expect_end(&self) -> Result<(), ParseError> {
+        match self.peek() {
+            None => Ok(()),
+            Some(token) if token.kind == TokenKind::RParen => {
+                Err(ParseError::UnbalancedParen { position: token.position })
+            }
+            Some(token) if token.kind == TokenKind::Bar => Err(ParseError::UnmatchedBar { position: token.position }),
+            Some(token) => Err(ParseError::TrailingInput { found: token.first_char(), position: token.position }),
+        }
+    }
+
+    fn parse_expression(&mut self) -> Result<Expression, ParseError> {
         self.parse_add_sub()
     }
 
-    fn parse_add_sub(&mut self) -> Result<Expression, String> {
+    fn parse_add_sub(&mut self) -> Result<Expression, ParseError> {
         let mut left = self.parse_mul_div()?;
+        let mut links = 0;
 
-        while let Some(&c) = self.input.peek() {
-            if c.is_whitespace() {
-                self.input.next();
-                continue;
-            }
-            match c {
-                '+' => {
-                    self.input.next();
+        while let Some(token) = self.peek() {
+            match token.kind {
+                TokenKind::Plus => {
+                    self.chain_link(&mut links)?;
                     let right = self.parse_mul_div()?;
                     left = Expression::add(left, right);
                 }
-                '-' => {
-                    self.input.next();
+                TokenKind::Minus => {
+                    self.chain_link(&mut links)?;
                     let right = self.parse_mul_div()?;
                     left = Expression::subtract(left, right);
                 }
@@ -46,23 +374,20 @@ impl<'a> ExpressionParser<'a> {
         Ok(left)
     }
 
-    fn parse_mul_div(&mut self) -> Result<Expression, String> {
-        let mut left = self.parse_power()?;
+    fn parse_mul_div(&mut self) -> Result<Expression, ParseError> {
+        let mut left = self.parse_unary()?;
+        let mut links = 0;
 
-        while let Some(&c) = self.input.peek() {
-            if c.is_whitespace() {
-                self.input.next();
-                continue;
-            }
-            match c {
-                '*' => {
-                    self.input.next();
-                    let right = self.parse_power()?;
+        while let Some(token) = self.peek() {
+            match token.kind {
+                TokenKind::Star => {
+                    self.chain_link(&mut links)?;
+                    let right = self.parse_unary()?;
                     left = Expression::multiply(left, right);
                 }
-                '/' => {
-                    self.input.next();
-                    let right = self.parse_power()?;
+                TokenKind::Slash => {
+                    self.chain_link(&mut links)?;
+                    let right = self.parse_unary()?;
                     left = Expression::divide(left, right);
                 }
                 _ => break,
@@ -71,147 +396,530 @@ impl<'a> ExpressionParser<'a> {
         Ok(left)
     }
 
-    fn parse_power(&mut self) -> Result<Expression, String> {
-        let mut left = self.parse_primary()?;
+    // 所有嵌套（括号、函数参数、一元负号、指数）都要经过这里，在此统一计数
+    fn parse_unary(&mut self) -> Result<Expression, ParseError> {
+        self.nested(Self::parse_unary_operand)
+    }
 
-        while let Some(&c) = self.input.peek() {
-            if c.is_whitespace() {
-                self.input.next();
-                continue;
+    // 嵌套一层调用 parse，超过 max_depth 时报错而不是继续递归
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Expression, ParseError>) -> Result<Expression, ParseError> {
+        self.parse_link(1, parse)
+    }
+
+    // 乘方之后的后缀 !、^ 每多一个，得到的树就深一层，虽然解析时并不递归。
+    // 所以第 links 个运算符的右操作数按多嵌套 links 层解析，树的深度和括号嵌套一样受 max_depth 限制
+    fn parse_link(&mut self, links: usize, parse: fn(&mut Self) -> Result<Expression, ParseError>) -> Result<Expression, ParseError> {
+        self.check_depth(links)?;
+        self.depth += links;
+        let result = parse(self);
+        self.depth -= links;
+        result
+    }
+
+    fn check_depth(&self, links: usize) -> Result<(), ParseError> {
+        if self.depth + links > self.max_depth {
+            let position = self.peek().map_or(self.end, |token| token.position);
+            return Err(ParseError::TooDeep { limit: self.max_depth, position });
+        }
+        Ok(())
+    }
+
+    // 消耗运算链中的下一个二元运算符，超过 max_chain 个时报错
+    fn chain_link(&mut self, links: &mut usize) -> Result<(), ParseError> {
+        *links += 1;
+        if *links > self.max_chain {
+            let position = self.peek().map_or(self.end, |token| token.position);
+            return Err(ParseError::ChainTooLong { limit: self.max_chain, position });
+        }
+        self.advance();
+        Ok(())
+    }
+
+    fn parse_unary_operand(&mut self) -> Result<Expression, ParseError> {
+        match self.peek().map(|token| token.kind) {
+            Some(TokenKind::Minus) => {
+                self.advance();
+                // 一元负号的优先级低于乘方：-x^2 = -(x^2)
+                match self.parse_unary()? {
+                    Expression::Constant(c) => Ok(Expression::constant(-c)),
+                    operand => Ok(Expression::negate(operand)),
+                }
             }
-            if c == '^' {
-                self.input.next();
-                let right = self.parse_primary()?;
-                // 检查是否是负幂，如果是，不要转换为除法
-                left = Expression::power(left, right);
-            } else {
-                break;
+            Some(TokenKind::Plus) => {
+                self.advance();
+                self.parse_unary()
+            }
+            _ => self.parse_power(),
+        }
+    }
+
+    fn parse_power(&mut self) -> Result<Expression, ParseError> {
+        let mut left = self.parse_primary()?;
+        let mut links = 0;
+
+        while let Some(token) = self.peek() {
+            match token.kind {
+                TokenKind::Caret | TokenKind::DoubleStar => {
+                    self.advance();
+                    links += 1;
+                    let right = self.parse_link(links, Self::parse_exponent)?;
+                    // 负指数保留为乘方，不转换为除法
+                    left = Expression::power(left, right);
+                }
+                // 后缀阶乘，比乘方结合得更紧：x!^2 = (x!)^2
+                TokenKind::Bang => {
+                    links += 1;
+                    self.check_depth(links)?;
+                    self.advance();
+                    left = Expression::factorial(left);
+                }
+                _ => break,
             }
         }
         Ok(left)
     }
 
-    fn parse_primary(&mut self) -> Result<Expression, String> {
-        self.skip_whitespace();
-        match self.input.peek() {
-            Some(&c) => {
-                match c {
-                    '0'..='9' => self.parse_number(),
-                    '(' => {
-                        self.input.next();
-                        let expr = self.parse_expression()?;
-                        self.expect_char(')')?;
-                        Ok(expr)
-                    }
-                    'a'..='z' | 'A'..='Z' => {
-                        // 先尝试解析函数名
-                        let name = self.parse_identifier()?;
-                        match name.as_str() {
-                            // 三角函数
-                            "sin" => self.parse_function(Expression::sin),
-                            "cos" => self.parse_function(Expression::cos),
-                            "tan" => self.parse_function(Expression::tan),
-                            // 反三角函数
-                            "arcsin" => self.parse_function(Expression::arcsin),
-                            "arccos" => self.parse_function(Expression::arccos),
-                            "arctan" => self.parse_function(Expression::arctan),
-                            // 自然对数和指数
-                            "ln" => self.parse_function(Expression::ln),
-                            "exp" => self.parse_function(Expression::exp),
-                            "e" => {
-                                // 检查是否后面跟着^，如果是则解析为自然指数
-                                if let Some('^') = self.input.peek() {
-                                    self.input.next(); // 消耗^
-                                    let power = self.parse_primary()?;
-                                    Ok(Expression::exp(power))
-                                } else {
-                                    Ok(Expression::constant(std::f64::consts::E))
-                                }
-                            }
-                            // 双曲函数
-                            "sinh" => self.parse_function(Expression::sinh),
-                            "cosh" => self.parse_function(Expression::cosh),
-                            "tanh" => self.parse_function(Expression::tanh),
-                            // 如果不是函数名，就当作变量
-                            _ => Ok(Expression::variable(&name))
+    // 解析 ^（或 **）之后的指数：允许带一元负号（x^-2 = Power(x, -2)，负号后的数字直接折叠成负常数），
+    // 并且乘方右结合：x^2^3 = x^(2^3)。指数不包含乘除，x^1/2 = (x^1)/2、x^2*y = (x^2)*y，
+    // 分数指数必须写括号：x^(1/2)
+    fn parse_exponent(&mut self) -> Result<Expression, ParseError> {
+        match self.peek() {
+            None => Err(ParseError::MissingExponent { found: None, position: self.end }),
+            Some(token) if matches!(token.kind, TokenKind::Star | TokenKind::Slash | TokenKind::Caret | TokenKind::DoubleStar | TokenKind::RParen) => {
+                Err(ParseError::MissingExponent { found: Some(token.first_char()), position: token.position })
+            }
+            _ => self.parse_unary(),
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, ParseError> {
+        let token = self.peek().ok_or_else(|| self.unexpected_eof())?;
+        match token.kind {
+            TokenKind::Number(text) => {
+                self.advance();
+                match text.parse::<f64>() {
+                    Ok(n) => Ok(Expression::constant(n)),
+                    Err(_) => Err(ParseError::InvalidNumber { text: text.to_string(), position: token.position }),
+                }
+            }
+            TokenKind::LParen => {
+                self.advance();
+                let expr = self.parse_expression()?;
+                self.expect_closing(token.position)?;
+                Ok(expr)
+            }
+            // 绝对值 |x|：'|' 出现在需要操作数的位置时开启一组，出现在需要运算符的位置时
+            // 关闭最内层尚未关闭的一组。因此 |a| + |b| 是两组，||x| - 1| 是嵌套的两组；
+            // 竖线必须成对出现，组内可以是任意表达式，包括函数调用和括号
+            TokenKind::Bar => {
+                self.advance();
+                let expr = self.parse_expression()?;
+                match self.peek() {
+                    Some(next) if next.kind == TokenKind::Bar => self.advance(),
+                    Some(next) => return Err(next.unexpected()),
+                    None => return Err(ParseError::UnmatchedBar { position: token.position }),
+                }
+                Ok(Expression::abs(expr))
+            }
+            // 函数名不区分大小写（SIN(x)、Sin(x) 都是 sin(x)），变量名区分大小写（x 和 X 是两个变量）
+            TokenKind::Identifier(name) => {
+                self.advance();
+                let function = name.to_ascii_lowercase();
+                if let Some(expected) = function_arity(&function) {
+                    let args = match self.peek() {
+                        Some(next) if next.kind == TokenKind::LParen => self.parse_function_args()?,
+                        // 省略括号时参数本身也可能是函数（sin cos x），同样计入嵌套深度
+                        Some(_) if self.implicit_application && expected == 1 => vec![self.nested(Self::parse_primary)?],
+                        next => {
+                            let position = next.map_or(self.end, |next| next.position);
+                            return Err(ParseError::MissingFunctionParen { name: name.to_string(), position });
                         }
+                    };
+                    // log(x) 只有一个参数时是自然对数，与 ln(x) 相同；log(x, b) 以 b 为底
+                    if function == "log" && args.len() == 1 {
+                        return Ok(build_function("ln", args));
+                    }
+                    if args.len() != expected {
+                        return Err(ParseError::WrongArity {
+                            name: name.to_string(),
+                            expected,
+                            found: args.len(),
+                            position: token.position,
+                        });
+                    }
+                    return Ok(build_function(&function, args));
+                }
+                if name == "e" {
+                    // 检查是否后面跟着^，如果是则解析为自然指数
+                    if self.eat(TokenKind::Caret) || self.eat(TokenKind::DoubleStar) {
+                        let power = self.parse_exponent()?;
+                        return Ok(Expression::exp(power));
                     }
-                    _ => Err(format!("Unexpected character: {}", c)),
+                    return Ok(Expression::constant(std::f64::consts::E));
+                }
+                // 如果不是函数名，就当作变量
+                match greek_letter(name) {
+                    Some(letter) if self.greek_letters => Ok(Expression::variable(letter)),
+                    _ => Ok(Expression::variable(name)),
                 }
             }
-            None => Err("Unexpected end of input".to_string()),
+            _ => Err(token.unexpected()),
         }
     }
 
-    fn parse_identifier(&mut self) -> Result<String, String> {
-        let mut name = String::new();
-        while let Some(&c) = self.input.peek() {
-            if c.is_alphanumeric() {
-                name.push(c);
-                self.input.next();
-            } else {
-                break;
-            }
+    // 解析函数名之后括号里以逗号分隔的参数列表
+    fn parse_function_args(&mut self) -> Result<Vec<Expression>, ParseError> {
+        let open = match self.peek() {
+            Some(token) if token.kind == TokenKind::LParen => token.position,
+            Some(token) => return Err(token.unexpected()),
+            None => return Err(self.unexpected_eof()),
+        };
+        self.advance(); // 消耗左括号
+
+        let mut args = Vec::new();
+        if self.eat(TokenKind::RParen) {
+            return Ok(args);
         }
-        if name.is_empty() {
-            Err("Expected identifier".to_string())
-        } else {
-            Ok(name)
+        loop {
+            args.push(self.parse_expression()?);
+            if !self.eat(TokenKind::Comma) {
+                self.expect_closing(open)?;
+                return Ok(args);
+            }
         }
     }
 
-    fn parse_function<F>(&mut self, constructor: F) -> Result<Expression, String>
-    where
-        F: FnOnce(Expression) -> Expression,
-    {
-        self.skip_whitespace();
-        match self.input.peek() {
-            Some('(') => {
-                self.input.next(); // 消耗左括号
-                let expr = self.parse_expression()?;
-                self.expect_char(')')?;
-                Ok(constructor(expr))
+    // 期望与下标 open 处的 '(' 配对的 ')'
+    fn expect_closing(&mut self, open: usize) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(token) if token.kind == TokenKind::RParen => {
+                self.advance();
+                Ok(())
             }
-            _ => Err("Expected '(' after function name".to_string())
+            Some(token) => Err(token.unexpected()),
+            None => Err(ParseError::UnbalancedParen { position: open }),
         }
     }
 
-    fn parse_number(&mut self) -> Result<Expression, String> {
-        let mut number = String::new();
-        while let Some(&c) = self.input.peek() {
-            if c.is_digit(10) || c == '.' {
-                number.push(c);
-                self.input.next();
-            } else {
-                break;
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.next).copied()
+    }
+
+    fn advance(&mut self) {
+        self.next += 1;
+    }
+
+    // 下一个词法单元是 kind 时消耗它并返回 true
+    fn eat(&mut self, kind: TokenKind) -> bool {
+        match self.peek() {
+            Some(token) if token.kind == kind => {
+                self.advance();
+                true
             }
+            _ => false,
+        }
+    }
+
+    fn unexpected_eof(&self) -> ParseError {
+        ParseError::UnexpectedEof { position: self.end }
+    }
+}
+
+// 已知函数的参数个数，不是函数名时返回 None
+fn function_arity(name: &str) -> Option<usize> {
+    match name {
+        "sin" | "cos" | "tan" | "sec" | "csc" | "cot" |
+        "arcsin" | "arccos" | "arctan" |
+        "sqrt" | "cbrt" | "ln" | "exp" | "log10" | "log2" |
+        "sinh" | "cosh" | "tanh" | "arctanh" | "digamma" | "abs" | "heaviside" | "step" => Some(1),
+        "log" | "polygamma" | "min" | "max" | "root" => Some(2),
+        "piecewise" | "clamp" => Some(3),
+        _ => None,
+    }
+}
+
+fn greek_letter(name: &str) -> Option<&'static str> {
+    let letter = match name {
+        "alpha" => "α",
+        "beta" => "β",
+        "gamma" => "γ",
+        "delta" => "δ",
+        "epsilon" => "ε",
+        "zeta" => "ζ",
+        "eta" => "η",
+        "theta" => "θ",
+        "iota" => "ι",
+        "kappa" => "κ",
+        "lambda" => "λ",
+        "mu" => "μ",
+        "nu" => "ν",
+        "xi" => "ξ",
+        "rho" => "ρ",
+        "sigma" => "σ",
+        "tau" => "τ",
+        "phi" => "φ",
+        "chi" => "χ",
+        "psi" => "ψ",
+        "omega" => "ω",
+        _ => return None,
+    };
+    Some(letter)
+}
+
+// 参数个数已由调用方按 function_arity 检查过
+fn build_function(name: &str, args: Vec<Expression>) -> Expression {
+    let mut args = args.into_iter();
+    let mut arg = || args.next().expect("argument count is checked by the caller");
+    match name {
+        // 三角函数
+        "sin" => Expression::sin(arg()),
+        "cos" => Expression::cos(arg()),
+        "tan" => Expression::tan(arg()),
+        "sec" => Expression::sec(arg()),
+        "csc" => Expression::csc(arg()),
+        "cot" => Expression::cot(arg()),
+        // 反三角函数
+        "arcsin" => Expression::arcsin(arg()),
+        "arccos" => Expression::arccos(arg()),
+        "arctan" => Expression::arctan(arg()),
+        // 自然对数和指数
+        "sqrt" => Expression::sqrt(arg()),
+        // root(x, n) 是 n 次方根
+        "root" => {
+            let x = arg();
+            Expression::root(x, arg())
         }
-        match number.parse::<f64>() {
-            Ok(n) => Ok(Expression::constant(n)),
-            Err(_) => Err(format!("Invalid number: {}", number)),
+        "cbrt" => Expression::root(arg(), Expression::constant(3.0)),
+        "ln" => Expression::ln(arg()),
+        "exp" => Expression::exp(arg()),
+        // log(x, b) 是以 b 为底的对数：真数在前、底数在后，与 Log(底数, 真数) 的存储顺序相反
+        "log" => {
+            let x = arg();
+            Expression::log(arg(), x)
         }
+        "log10" => Expression::log(Expression::constant(10.0), arg()),
+        "log2" => Expression::log(Expression::constant(2.0), arg()),
+        // 双曲函数
+        "sinh" => Expression::sinh(arg()),
+        "cosh" => Expression::cosh(arg()),
+        "tanh" => Expression::tanh(arg()),
+        "arctanh" => Expression::arctanh(arg()),
+        "abs" => Expression::abs(arg()),
+        // 单位阶跃函数，step 是 heaviside 的别名
+        "heaviside" | "step" => Expression::heaviside(arg()),
+        // polygamma(n, x) 是 n 阶多伽马函数
+        "digamma" => Expression::digamma(arg()),
+        "polygamma" => {
+            let order = arg();
+            Expression::polygamma(order, arg())
+        }
+        // 分段函数，piecewise(c, a, b) 在 c >= 0 时取 a，否则取 b
+        "min" => {
+            let left = arg();
+            Expression::min(left, arg())
+        }
+        "max" => {
+            let left = arg();
+            Expression::max(left, arg())
+        }
+        "piecewise" => {
+            let condition = arg();
+            let if_nonnegative = arg();
+            Expression::piecewise(condition, if_nonnegative, arg())
+        }
+        // clamp(x, lo, hi) 把 x 限制在 [lo, hi] 内
+        "clamp" => {
+            let x = arg();
+            let lo = arg();
+            Expression::clamp(x, lo, arg())
+        }
+        _ => unreachable!("'{}' is not a known function", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculus::Differentiate;
+
+    // 线性同余生成器，让模糊测试可重复
+    fn pseudo_random(seed: &mut u64) -> usize {
+        *seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (*seed >> 33) as usize
     }
 
-    fn expect_char(&mut self, expected: char) -> Result<(), String> {
-        self.skip_whitespace();
-        match self.input.next() {
-            Some(c) if c == expected => Ok(()),
-            Some(c) => Err(format!("Expected '{}', found '{}'", expected, c)),
-            None => Err(format!("Expected '{}', found end of input", expected)),
+    #[test]
+    fn deep_input_is_a_clean_error() {
+        let nested = [
+            format!("{}x{}", "(".repeat(10_000), ")".repeat(10_000)),
+            format!("{}x{}", "sin(".repeat(10_000), ")".repeat(10_000)),
+            format!("{}x", "-".repeat(10_000)),
+            vec!["x"; 200_000].join("^"),
+            format!("x{}", "!".repeat(200_000)),
+        ];
+        for input in &nested {
+            match parse(input) {
+                Err(ParseError::TooDeep { limit, .. }) => assert_eq!(limit, DEFAULT_MAX_DEPTH),
+                other => panic!("{}...: expected TooDeep, got {:?}", &input[..20], other.map(|e| e.depth())),
+            }
+        }
+
+        for input in [vec!["x"; 200_000].join("+"), vec!["x"; 200_000].join("*")] {
+            match parse(&input) {
+                Err(ParseError::ChainTooLong { limit, position }) => {
+                    assert_eq!(limit, DEFAULT_MAX_CHAIN);
+                    assert_eq!(position, 2 * DEFAULT_MAX_CHAIN + 1);
+                }
+                other => panic!("{}...: expected ChainTooLong, got {:?}", &input[..20], other.map(|e| e.depth())),
+            }
         }
     }
 
-    fn skip_whitespace(&mut self) {
-        while let Some(&c) = self.input.peek() {
-            if c.is_whitespace() {
-                self.input.next();
-            } else {
-                break;
+    #[test]
+    fn random_deep_input_never_panics() {
+        let pieces = ["(", ")", "sin(", "-", "x", "2", "+", "*", "/", "^", "!", "|", ",", "max("];
+        let mut seed = 42;
+        for _ in 0..200 {
+            let length = 1 + pseudo_random(&mut seed) % 5_000;
+            let input: String = (0..length).map(|_| pieces[pseudo_random(&mut seed) % pieces.len()]).collect();
+            if let Ok(expr) = parse(&input) {
+                expr.simplify();
             }
         }
     }
 
-    fn peek(&self) -> Option<char> {
-        self.input.clone().next()
+    // 默认上限内能解析的最深的树和最长的运算链，simplify、求导后再 simplify 都不会栈溢出
+    #[test]
+    fn deepest_accepted_trees_are_safe_to_simplify_and_differentiate() {
+        let nested: [fn(usize) -> String; 3] = [
+            |n| format!("{}x{}", "(".repeat(n), ")".repeat(n)),
+            |n| format!("{}x{}", "sin(".repeat(n), ")".repeat(n)),
+            |n| format!("{}x", "-".repeat(n)),
+        ];
+        for shape in nested {
+            let deepest = (1..).map(|n| parse(&shape(n))).take_while(Result::is_ok).last().unwrap().unwrap();
+            assert!(deepest.depth() <= DEFAULT_MAX_DEPTH + 1);
+            deepest.simplify();
+            deepest.derivative("x");
+        }
+
+        // 链上有 DEFAULT_MAX_CHAIN 个运算符
+        let terms = DEFAULT_MAX_CHAIN + 1;
+        for input in [vec!["sin(x)"; terms].join("+"), vec!["x"; terms].join("*"), vec!["x"; terms].join("-")] {
+            let longest = parse(&input).unwrap();
+            assert!(longest.depth() > DEFAULT_MAX_CHAIN);
+            longest.simplify();
+            longest.derivative("x");
+        }
+        let quotients = parse(&vec!["x"; terms].join("/")).unwrap();
+        assert_eq!(quotients.simplify().to_string(), format!("1 / x^{}", terms - 2));
+        quotients.differentiate("x");
+    }
+
+    #[test]
+    fn unary_minus_builds_negate() {
+        let x = Expression::variable("x");
+        assert_eq!(parse("-x").unwrap(), Expression::negate(x.clone()));
+        assert_eq!(parse("-x^2").unwrap(), Expression::negate(Expression::power(x.clone(), Expression::constant(2.0))));
+        assert_eq!(parse("--x").unwrap(), Expression::negate(Expression::negate(x)));
+        assert_eq!(parse("-2").unwrap(), Expression::constant(-2.0));
+    }
+
+    #[test]
+    fn missing_exponent_is_reported() {
+        assert_eq!(parse("x^"), Err(ParseError::MissingExponent { found: None, position: 2 }));
+        assert_eq!(parse("(x^)"), Err(ParseError::MissingExponent { found: Some(')'), position: 3 }));
+        assert_eq!(parse("x^*2"), Err(ParseError::MissingExponent { found: Some('*'), position: 2 }));
+        assert_eq!(parse("e^"), Err(ParseError::MissingExponent { found: None, position: 2 }));
+        assert_eq!(parse("x**").unwrap_err().to_string(), "Expected exponent after '^', found end of input at position 3");
+    }
+
+    #[test]
+    fn log_takes_one_or_two_arguments() {
+        let x = Expression::variable("x");
+        assert_eq!(parse("log(x)").unwrap(), Expression::ln(x.clone()));
+        assert_eq!(parse("log(x, 2)").unwrap(), Expression::log(Expression::constant(2.0), x));
+        assert!(matches!(parse("log(x, 2, 3)"), Err(ParseError::WrongArity { expected: 2, found: 3, .. })));
+    }
+
+    #[test]
+    fn max_depth_is_configurable() {
+        assert!(ExpressionParser::new("((x))").with_max_depth(3).parse().is_ok());
+        assert!(matches!(
+            ExpressionParser::new("((x))").with_max_depth(2).parse(),
+            Err(ParseError::TooDeep { limit: 2, position: 2 })
+        ));
+    }
+
+    // 运算链的长度与嵌套层数分开限制，宽的和式、乘积不受 max_depth 影响
+    #[test]
+    fn max_chain_is_configurable() {
+        let sum = vec!["x"; 100].join("+");
+        assert!(parse(&sum).is_ok());
+        assert!(ExpressionParser::new(&sum).with_max_depth(2).parse().is_ok());
+        assert!(parse(&vec!["x"; 70].join("*")).is_ok());
+        assert_eq!(
+            ExpressionParser::new(&sum).with_max_chain(50).parse(),
+            Err(ParseError::ChainTooLong { limit: 50, position: 101 })
+        );
+        assert!(ExpressionParser::new("x*y+z*w").with_max_chain(1).parse().is_ok());
+    }
+
+    // build_sum 拼出的宽和式打印之后能原样解析回来
+    #[test]
+    fn wide_sums_round_trip_through_display() {
+        let terms: Vec<Expression> = (0..500)
+            .map(|i| Expression::multiply(Expression::constant(i as f64 + 2.0), Expression::variable(&format!("x{}", i))))
+            .collect();
+        let sum = crate::simplify::build_sum(&terms);
+        let printed = sum.to_string();
+        assert!(parse(&printed).unwrap() == sum, "{}", printed);
+    }
+
+    #[test]
+    fn exponent_may_be_unary() {
+        let x = Expression::variable("x");
+        assert_eq!(parse("x^-1").unwrap(), Expression::power(x.clone(), Expression::constant(-1.0)));
+        assert_eq!(parse("x^-1").unwrap(), parse("x^(-1)").unwrap());
+        assert_eq!(parse("x^-x").unwrap(), Expression::power(x.clone(), Expression::negate(x.clone())));
+        assert_eq!(parse("x^-sin(x)").unwrap(), Expression::power(x.clone(), Expression::negate(Expression::sin(x))));
+    }
+
+    #[test]
+    fn whitespace_around_caret_is_skipped() {
+        let square = Expression::power(Expression::variable("x"), Expression::constant(2.0));
+        for input in ["x ^ 2", "x\n^2", "x^\t2", "x    ^    2", " x ^ 2 "] {
+            assert_eq!(parse(input).unwrap(), square, "{:?}", input);
+        }
+        assert_eq!(parse("x ^"), Err(ParseError::MissingExponent { found: None, position: 3 }));
+        assert_eq!(parse("x ^ )"), Err(ParseError::MissingExponent { found: Some(')'), position: 4 }));
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_character() {
+        assert_eq!(parse("x + )"), Err(ParseError::UnexpectedChar { found: ')', position: 4 }));
+        for (input, position, found) in [
+            ("x + )", 4, Some(')')),
+            ("x $ y", 2, Some('$')),
+            ("x + 1)", 5, None),
+            ("2 * (x + 1", 4, None),
+            ("3.4.5", 0, Some('3')),
+            ("", 0, None),
+        ] {
+            let error = parse(input).unwrap_err();
+            assert_eq!((error.position(), error.found()), (position, found), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn negative_and_fractional_exponents() {
+        let x = Expression::variable("x");
+        let inverse_square = parse("x^-2").unwrap();
+        assert_eq!(inverse_square, Expression::power(x.clone(), Expression::constant(-2.0)));
+        assert_eq!(inverse_square.derivative("x").to_string(), "-2 * x^-3");
+        // 分数指数必须写括号
+        assert_eq!(parse("x^1/2").unwrap(), Expression::divide(Expression::power(x.clone(), Expression::constant(1.0)), Expression::constant(2.0)));
+        assert_eq!(parse("x^(1/2)").unwrap(), Expression::power(x.clone(), Expression::divide(Expression::constant(1.0), Expression::constant(2.0))));
+        assert_eq!(parse("x^-0.5").unwrap(), Expression::power(x, Expression::constant(-0.5)));
     }
 }
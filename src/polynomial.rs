@@ -0,0 +1,227 @@
+use crate::expression::Expression;
+use crate::simplify::{build_sum, scaled};
+use std::collections::HashMap;
+
+impl Expression {
+    // 提取关于 var 的多项式系数，下标即次数；不是多项式时返回 None
+    pub fn poly_coeffs(&self, var: &str) -> Option<Vec<f64>> {
+        let mut coeffs = match self {
+            Expression::Constant(c) => vec![*c],
+            Expression::Variable(name) if name == var => vec![0.0, 1.0],
+            Expression::Add(left, right) => poly_add(&left.poly_coeffs(var)?, &right.poly_coeffs(var)?, 1.0),
+            Expression::Subtract(left, right) => poly_add(&left.poly_coeffs(var)?, &right.poly_coeffs(var)?, -1.0),
+            Expression::Negate(expr) => expr.poly_coeffs(var)?.iter().map(|c| -c).collect(),
+            Expression::Multiply(left, right) => poly_mul(&left.poly_coeffs(var)?, &right.poly_coeffs(var)?),
+            Expression::Divide(left, right) => {
+                // 只允许除以非零常数
+                match right.poly_coeffs(var)?.as_slice() {
+                    [d] if *d != 0.0 => left.poly_coeffs(var)?.iter().map(|c| c / d).collect(),
+                    _ => return None,
+                }
+            }
+            Expression::Power(base, exponent) => match **exponent {
+                Expression::Constant(n) if n >= 0.0 && n.fract() == 0.0 => {
+                    let base = base.poly_coeffs(var)?;
+                    (0..n as usize).fold(vec![1.0], |acc, _| poly_mul(&acc, &base))
+                }
+                _ => return None,
+            },
+            // 不含变量的子表达式（如 sqrt(2)、ln(3)）能求值时当作常数系数
+            _ if self.variables().is_empty() => match self.evaluate(&HashMap::new()) {
+                Ok(c) => vec![c],
+                Err(_) => return None,
+            },
+            _ => return None,
+        };
+
+        // 去掉高次的零系数，至少保留常数项
+        while coeffs.len() > 1 && coeffs.last() == Some(&0.0) {
+            coeffs.pop();
+        }
+        Some(coeffs)
+    }
+
+    // 关于 var 的多项式按降幂排列、合并同类项后的字符串，如 5 - x + x^3 + 2*x^2 => x^3 + 2 * x^2 - x + 5；
+    // 不是多项式时返回 None
+    pub fn as_polynomial_string(&self, var: &str) -> Option<String> {
+        self.poly_coeffs(var).map(|coeffs| poly_to_expression(&coeffs, var).to_string())
+    }
+
+    // 改写为 Horner 嵌套形式，如 x^3 + 2*x^2 + 3*x + 4 → ((x + 2)*x + 3)*x + 4
+    pub fn horner_form(&self, var: &str) -> Option<Expression> {
+        let coeffs = self.poly_coeffs(var)?;
+        let (leading, rest) = coeffs.split_last()?;
+        let x = Expression::variable(var);
+
+        let mut result = Expression::constant(*leading);
+        for &c in rest.iter().rev() {
+            result = match result {
+                Expression::Constant(1.0) => x.clone(),
+                Expression::Constant(-1.0) => Expression::negate(x.clone()),
+                _ => Expression::multiply(result, x.clone()),
+            };
+            if c != 0.0 {
+                result = Expression::add(result, Expression::constant(c));
+            }
+        }
+        Some(result)
+    }
+}
+
+fn poly_add(a: &[f64], b: &[f64], sign: f64) -> Vec<f64> {
+    (0..a.len().max(b.len()))
+        .map(|i| a.get(i).copied().unwrap_or(0.0) + sign * b.get(i).copied().unwrap_or(0.0))
+        .collect()
+}
+
+fn poly_mul(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let mut result = vec![0.0; a.len() + b.len() - 1];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            result[i + j] += x * y;
+        }
+    }
+    result
+}
+
+// 由系数重建展开的多项式，高次项在前
+pub(crate) fn poly_to_expression(coeffs: &[f64], var: &str) -> Expression {
+    let terms: Vec<Expression> = coeffs
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, c)| **c != 0.0)
+        .map(|(degree, c)| {
+            let power = match degree {
+                0 => return Expression::constant(*c),
+                1 => Expression::variable(var),
+                _ => Expression::power(Expression::variable(var), Expression::constant(degree as f64)),
+            };
+            scaled(*c, power)
+        })
+        .collect();
+    build_sum(&terms)
+}
+
+pub(crate) fn poly_eval(coeffs: &[f64], x: f64) -> f64 {
+    coeffs.iter().rev().fold(0.0, |acc, c| acc * x + c)
+}
+
+pub(crate) fn poly_derivative(coeffs: &[f64]) -> Vec<f64> {
+    match coeffs.len() {
+        0 | 1 => vec![0.0],
+        _ => coeffs.iter().enumerate().skip(1).map(|(i, c)| i as f64 * c).collect(),
+    }
+}
+
+// 多项式长除法，返回 (商, 余式)；除式的最高次系数不能为零
+pub(crate) fn poly_divmod(num: &[f64], den: &[f64]) -> (Vec<f64>, Vec<f64>) {
+    let mut rem = num.to_vec();
+    if num.len() < den.len() {
+        return (vec![0.0], rem);
+    }
+    let lead = den[den.len() - 1];
+    let mut quot = vec![0.0; num.len() - den.len() + 1];
+    for shift in (0..quot.len()).rev() {
+        let factor = rem[shift + den.len() - 1] / lead;
+        quot[shift] = factor;
+        for (i, d) in den.iter().enumerate() {
+            rem[shift + i] -= factor * d;
+        }
+    }
+    rem.truncate(den.len() - 1);
+    if rem.is_empty() {
+        rem.push(0.0);
+    }
+    (quot, rem)
+}
+
+// 求多项式的全部根（Durand-Kerner 迭代），再用牛顿法精修实根。
+// 存在复根时返回 None；重根只能得到近似值，由调用方判断
+pub(crate) fn poly_real_roots(coeffs: &[f64]) -> Option<Vec<f64>> {
+    let degree = coeffs.len().checked_sub(1)?;
+    let lead = coeffs[degree];
+    let monic: Vec<f64> = coeffs.iter().map(|c| c / lead).collect();
+
+    type Complex = (f64, f64);
+    let mul = |a: Complex, b: Complex| (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0);
+    let div = |a: Complex, b: Complex| {
+        let norm = b.0 * b.0 + b.1 * b.1;
+        ((a.0 * b.0 + a.1 * b.1) / norm, (a.1 * b.0 - a.0 * b.1) / norm)
+    };
+    let eval = |z: Complex| monic.iter().rev().fold((0.0, 0.0), |acc, c| {
+        let (re, im) = mul(acc, z);
+        (re + c, im)
+    });
+
+    // 初值取在复平面的螺旋上，避免对称的多项式卡住
+    let mut roots: Vec<Complex> = (0..degree)
+        .map(|k| {
+            let z = (0.4, 0.9);
+            (0..k).fold((1.0, 0.0), |acc, _| mul(acc, z))
+        })
+        .collect();
+    for _ in 0..500 {
+        let mut delta: f64 = 0.0;
+        for i in 0..degree {
+            let denom = (0..degree)
+                .filter(|&j| j != i)
+                .fold((1.0, 0.0), |acc, j| mul(acc, (roots[i].0 - roots[j].0, roots[i].1 - roots[j].1)));
+            let step = div(eval(roots[i]), denom);
+            roots[i] = (roots[i].0 - step.0, roots[i].1 - step.1);
+            delta = delta.max(step.0.abs() + step.1.abs());
+        }
+        if delta < 1e-14 {
+            break;
+        }
+    }
+
+    let derivative = poly_derivative(coeffs);
+    roots
+        .into_iter()
+        .map(|(re, im)| {
+            if im.abs() > 1e-7 * re.abs().max(1.0) {
+                return None;
+            }
+            let mut x = re;
+            for _ in 0..3 {
+                let slope = poly_eval(&derivative, x);
+                if slope == 0.0 {
+                    break;
+                }
+                x -= poly_eval(coeffs, x) / slope;
+            }
+            Some(x)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse;
+    use std::collections::HashMap;
+
+    #[test]
+    fn horner_form_nests_the_coefficients() {
+        let poly = parse("x^3 + 2*x^2 + 3*x + 4").unwrap();
+        let horner = poly.horner_form("x").unwrap();
+        assert_eq!(horner.to_string(), "((x + 2) * x + 3) * x + 4");
+        assert_eq!(parse("x^2 - 1").unwrap().horner_form("x").unwrap().to_string(), "x * x - 1");
+        assert_eq!(parse("-x^2 + 1").unwrap().horner_form("x").unwrap().to_string(), "-x * x + 1");
+        assert!(parse("sin(x) + 1").unwrap().horner_form("x").is_none());
+    }
+
+    #[test]
+    fn horner_form_evaluates_like_the_expanded_polynomial() {
+        for input in ["x^3 + 2*x^2 + 3*x + 4", "-x^4 + 0.5*x - 7", "3*(x - 1)^3", "5"] {
+            let poly = parse(input).unwrap();
+            let horner = poly.horner_form("x").unwrap();
+            for x in [-2.5, -1.0, 0.0, 0.3, 1.0, 4.0] {
+                let bindings = HashMap::from([("x".to_string(), x)]);
+                let expected = poly.evaluate(&bindings).unwrap();
+                let actual = horner.evaluate(&bindings).unwrap();
+                assert!((expected - actual).abs() < 1e-9 * expected.abs().max(1.0), "{} at {}", input, x);
+            }
+        }
+    }
+}
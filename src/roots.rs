@@ -0,0 +1,172 @@
+use crate::calculus::Differentiate;
+use crate::evaluate::EvalError;
+use crate::expression::Expression;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RootError {
+    // 在某个迭代点上求值失败，如超出定义域
+    Eval(EvalError),
+    ZeroDerivative { at: f64 },
+    // 迭代点变成无穷大或 NaN
+    Diverged { initial: f64 },
+    NoConvergence { iterations: usize, estimate: f64 },
+}
+
+impl fmt::Display for RootError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RootError::Eval(error) => write!(f, "Evaluation failed: {}", error),
+            RootError::ZeroDerivative { at } => write!(f, "Derivative is zero at {}", at),
+            RootError::Diverged { initial } => write!(f, "Newton iteration diverged from {}", initial),
+            RootError::NoConvergence { iterations, estimate } => {
+                write!(f, "No convergence after {} iterations (last estimate {})", iterations, estimate)
+            }
+        }
+    }
+}
+
+impl Error for RootError {}
+
+impl From<EvalError> for RootError {
+    fn from(error: EvalError) -> Self {
+        RootError::Eval(error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SolveError {
+    NotPolynomial { var: String },
+    // 方程恒成立（0 = 0），var 取任何值都是解
+    InfinitelyManySolutions { var: String },
+    // 只支持一次和二次方程
+    UnsupportedDegree(usize),
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SolveError::NotPolynomial { var } => write!(f, "Not a polynomial in {}", var),
+            SolveError::InfinitelyManySolutions { var } => write!(f, "Every value of {} is a solution", var),
+            SolveError::UnsupportedDegree(degree) => write!(f, "Cannot solve equations of degree {}", degree),
+        }
+    }
+}
+
+impl Error for SolveError {}
+
+impl Expression {
+    // 牛顿法求 f(var) = 0 的根：x -= f(x) / f'(x)，导数由符号求导得到。
+    // 步长或 |f(x)| 小于 tol 时视为收敛
+    pub fn find_root(&self, var: &str, initial: f64, tol: f64, max_iter: usize) -> Result<f64, RootError> {
+        let derivative = self.differentiate(var).simplify();
+        let mut bindings = HashMap::new();
+        let mut x = initial;
+
+        for _ in 0..max_iter {
+            bindings.insert(var.to_string(), x);
+            let fx = self.evaluate(&bindings)?;
+            if fx.abs() <= tol {
+                return Ok(x);
+            }
+            let slope = derivative.evaluate(&bindings)?;
+            if slope == 0.0 {
+                return Err(RootError::ZeroDerivative { at: x });
+            }
+
+            let step = fx / slope;
+            x -= step;
+            if !x.is_finite() {
+                return Err(RootError::Diverged { initial });
+            }
+            if step.abs() <= tol {
+                return Ok(x);
+            }
+        }
+
+        Err(RootError::NoConvergence { iterations: max_iter, estimate: x })
+    }
+
+    // 求解 self = 0，只支持关于 var 的一次和二次多项式。
+    // 二次方程用求根公式，判别式不是完全平方数时保留 sqrt；没有实根时返回空列表
+    pub fn solve(&self, var: &str) -> Result<Vec<Expression>, SolveError> {
+        let coeffs = self
+            .poly_coeffs(var)
+            .ok_or_else(|| SolveError::NotPolynomial { var: var.to_string() })?;
+        match coeffs.as_slice() {
+            [0.0] => Err(SolveError::InfinitelyManySolutions { var: var.to_string() }),
+            [_] => Ok(Vec::new()),
+            [b, a] => Ok(vec![Expression::constant(-b / a)]),
+            [c, b, a] => {
+                // 让首项系数为正，分母 2a 也就为正
+                let (a, b, c) = if *a < 0.0 { (-a, -b, -c) } else { (*a, *b, *c) };
+                let discriminant = b * b - 4.0 * a * c;
+                if discriminant < 0.0 {
+                    return Ok(Vec::new());
+                }
+                if discriminant == 0.0 {
+                    return Ok(vec![Expression::constant(-b / (2.0 * a))]);
+                }
+                let sqrt = Expression::sqrt(Expression::constant(discriminant));
+                let root = |sign: f64| {
+                    let numerator = match b {
+                        0.0 => Expression::multiply(Expression::constant(sign), sqrt.clone()),
+                        _ if sign < 0.0 => Expression::subtract(Expression::constant(-b), sqrt.clone()),
+                        _ => Expression::add(Expression::constant(-b), sqrt.clone()),
+                    };
+                    Expression::divide(numerator, Expression::constant(2.0 * a)).simplify()
+                };
+                Ok(vec![root(-1.0), root(1.0)])
+            }
+            _ => Err(SolveError::UnsupportedDegree(coeffs.len() - 1)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RootError, SolveError};
+    use crate::evaluate::EvalError;
+    use crate::parse;
+
+    #[test]
+    fn newton_finds_square_root_of_two() {
+        let root = parse("x^2 - 2").unwrap().find_root("x", 1.0, 1e-12, 50).unwrap();
+        assert!((root - std::f64::consts::SQRT_2).abs() < 1e-10);
+    }
+
+    #[test]
+    fn newton_reports_typed_errors() {
+        let f = parse("x^2 - 2").unwrap();
+        assert_eq!(f.find_root("x", 0.0, 1e-12, 50), Err(RootError::ZeroDerivative { at: 0.0 }));
+        assert!(matches!(f.find_root("x", 100.0, 1e-12, 2), Err(RootError::NoConvergence { iterations: 2, .. })));
+        assert!(matches!(
+            parse("ln(x)").unwrap().find_root("x", -1.0, 1e-12, 50),
+            Err(RootError::Eval(EvalError::DomainError(_)))
+        ));
+    }
+
+    fn solutions(input: &str) -> Result<Vec<String>, SolveError> {
+        let roots = parse(input).unwrap().solve("x")?;
+        Ok(roots.iter().map(|root| root.to_string()).collect())
+    }
+
+    #[test]
+    fn solves_linear_and_quadratic_equations() {
+        assert_eq!(solutions("2*x + 4"), Ok(vec!["-2".to_string()]));
+        assert_eq!(solutions("x^2 - 5*x + 6"), Ok(vec!["2".to_string(), "3".to_string()]));
+        assert_eq!(solutions("x^2 + 2*x + 1"), Ok(vec!["-1".to_string()]));
+        assert_eq!(solutions("x^2 + 1"), Ok(Vec::new()));
+        let irrational = parse("x^2 - 2").unwrap().solve("x").unwrap();
+        assert!(irrational.iter().all(|root| root.to_string().contains("sqrt")));
+    }
+
+    #[test]
+    fn solve_reports_typed_errors() {
+        assert_eq!(solutions("sin(x)"), Err(SolveError::NotPolynomial { var: "x".to_string() }));
+        assert_eq!(solutions("x - x"), Err(SolveError::InfinitelyManySolutions { var: "x".to_string() }));
+        assert_eq!(solutions("x^3 - 1"), Err(SolveError::UnsupportedDegree(3)));
+    }
+}
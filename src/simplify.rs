@@ -1,50 +1,396 @@
-use crate::expression::Expression;
+use crate::expression::{as_fraction, Expression};
+use crate::factor::gcd;
+
+const SIMPLIFY_MAX_PASSES: usize = 32;
+
+// simplify_with 的规则分组，Default 与 simplify 的行为一致
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimplifyOptions {
+    // 两个常数直接运算时折叠为一个常数，如 2 + 3 => 5、sqrt(4) => 2、3! => 6
+    pub fold_constants: bool,
+    // 合并同类项与同底数幂、约分，如 x + x => 2 * x、x * x => x^2、(a*b)/b => a
+    pub collect_like_terms: bool,
+    // 和式中的平方恒等式，如 sin(x)^2 + cos(x)^2 => 1；依赖同类项合并
+    pub trig_identities: bool,
+    // simplify_inverse_trig 的规则，假定参数落在主值区间内
+    pub inverse_trig: bool,
+    // expand_logs 的规则，如 ln(a * b) => ln(a) + ln(b)
+    pub expand_logs: bool,
+    // expand_exp 的规则，如 e^(a + b) => e^a * e^b
+    pub expand_exp: bool,
+}
+
+impl Default for SimplifyOptions {
+    fn default() -> Self {
+        SimplifyOptions {
+            fold_constants: true,
+            collect_like_terms: true,
+            trig_identities: true,
+            inverse_trig: false,
+            expand_logs: false,
+            expand_exp: false,
+        }
+    }
+}
 
 impl Expression {
+    // 把嵌套的加减链展开为加数列表，被减去的项取相反系数。
+    // 沿左结合的链迭代，只对右操作数和 -u 递归
+    pub fn flatten_sum(&self) -> Vec<Expression> {
+        let mut rights = Vec::new();
+        let mut bottom = self;
+        while let Expression::Add(left, right) | Expression::Subtract(left, right) = bottom {
+            rights.push((&**right, matches!(bottom, Expression::Subtract(_, _))));
+            bottom = left;
+        }
+
+        let mut terms = match bottom {
+            Expression::Negate(inner) => inner.flatten_sum().iter().map(negate).collect(),
+            _ => vec![bottom.clone()],
+        };
+        for (right, subtracted) in rights.into_iter().rev() {
+            if subtracted {
+                terms.extend(right.flatten_sum().iter().map(negate));
+            } else {
+                terms.extend(right.flatten_sum());
+            }
+        }
+        terms
+    }
+
+    // 把嵌套的乘法链展开为因子列表，-u 视为 -1 * u。沿左结合的链迭代，只对右操作数和 -u 递归
+    pub fn flatten_product(&self) -> Vec<Expression> {
+        let mut rights = Vec::new();
+        let mut bottom = self;
+        while let Expression::Multiply(left, right) = bottom {
+            rights.push(&**right);
+            bottom = left;
+        }
+
+        let mut factors = match bottom {
+            Expression::Negate(inner) => {
+                let mut factors = vec![Expression::constant(-1.0)];
+                factors.extend(inner.flatten_product());
+                factors
+            }
+            _ => vec![bottom.clone()],
+        };
+        for right in rights.into_iter().rev() {
+            factors.extend(right.flatten_product());
+        }
+        factors
+    }
+
+    // 拆成 (c, t) 使 self = c * t：乘法链中的常数因子全部乘进 c，如 2 * x * 3 => (6, x)；
+    // 单个常数为 (c, 1)，不含常数因子时为 (1, self)
+    pub fn as_coeff_term(&self) -> (f64, Expression) {
+        let (constants, rest): (Vec<Expression>, Vec<Expression>) = self
+            .flatten_product()
+            .into_iter()
+            .partition(|factor| matches!(factor, Expression::Constant(_)));
+        if constants.is_empty() {
+            return (1.0, self.clone());
+        }
+        let coefficient = constants.iter().filter_map(|c| c.constant_value()).product();
+        (coefficient, build_product(&rest))
+    }
+
+    // 可选的反三角化简，默认的 simplify 不会调用：
+    //   tan(arctan(u)) = u、sin(arcsin(u)) = u、cos(arccos(u)) = u 在定义域内恒成立；
+    //   arctan(tan(u)) = u 仅当 u ∈ (-π/2, π/2)，arcsin(sin(u)) = u 仅当 u ∈ [-π/2, π/2]，
+    //   arccos(cos(u)) = u 仅当 u ∈ [0, π]，这里假定 u 落在主值区间内。
+    pub fn simplify_inverse_trig(&self) -> Expression {
+        let expr = self.map_children(|child| child.simplify_inverse_trig());
+        let inner = match &expr {
+            Expression::Sin(arg) => match &**arg { Expression::Arcsin(u) => Some(u), _ => None },
+            Expression::Cos(arg) => match &**arg { Expression::Arccos(u) => Some(u), _ => None },
+            Expression::Tan(arg) => match &**arg { Expression::Arctan(u) => Some(u), _ => None },
+            Expression::Arcsin(arg) => match &**arg { Expression::Sin(u) => Some(u), _ => None },
+            Expression::Arccos(arg) => match &**arg { Expression::Cos(u) => Some(u), _ => None },
+            Expression::Arctan(arg) => match &**arg { Expression::Tan(u) => Some(u), _ => None },
+            _ => None,
+        };
+        match inner {
+            Some(u) => (**u).clone(),
+            None => expr,
+        }
+    }
+
+    // 可选的对数展开，默认的 simplify 不会调用：
+    //   ln(a * b) = ln(a) + ln(b)、ln(a / b) = ln(a) - ln(b)、
+    //   ln(a^n) = n * ln(a)、ln(√[n](a)) = ln(a) / n，任意底数的 log 同理。
+    // 这些恒等式要求 a、b 为正，这里不检查定义域
+    pub fn expand_logs(&self) -> Expression {
+        let expr = self.map_children(|child| child.expand_logs());
+        match log_parts(&expr) {
+            Some((base, arg)) => expand_log(base, arg),
+            None => expr,
+        }
+    }
+
+    // expand_logs 的逆操作：c * ln(u) => ln(u^c)，ln(a) + ln(b) => ln(a * b)，
+    // ln(a) - ln(b) => ln(a / b)；只合并底数相同的对数
+    pub fn contract_logs(&self) -> Expression {
+        let expr = self.map_children(|child| child.contract_logs());
+        match &expr {
+            Expression::Multiply(left, right) => match (&**left, &**right) {
+                (Expression::Constant(c), log) | (log, Expression::Constant(c)) => match log_parts(log) {
+                    Some((base, arg)) => make_log(base, Expression::power(arg.clone(), Expression::constant(*c))),
+                    None => expr,
+                },
+                _ => expr,
+            },
+            Expression::Add(left, right) | Expression::Subtract(left, right) => {
+                match (log_parts(left), log_parts(right)) {
+                    (Some((b1, a1)), Some((b2, a2))) if b1 == b2 => {
+                        let arg = if matches!(expr, Expression::Add(_, _)) {
+                            Expression::multiply(a1.clone(), a2.clone())
+                        } else {
+                            Expression::divide(a1.clone(), a2.clone())
+                        };
+                        make_log(b1, arg)
+                    }
+                    _ => expr,
+                }
+            }
+            _ => expr,
+        }
+    }
+
+    // 可选的指数展开：e^(a + b) = e^a * e^b、e^(a - b) = e^a / e^b、
+    // e^(n * u) = (e^u)^n，其中 e^(n * ln(x)) = x^n
+    pub fn expand_exp(&self) -> Expression {
+        let expr = self.map_children(|child| child.expand_exp());
+        match &expr {
+            Expression::Exp(arg) => expand_exp_of(arg),
+            _ => expr,
+        }
+    }
+
+    // expand_exp 的逆操作：乘积中的 e^a * e^b => e^(a + b)，
+    // e^a / e^b => e^(a - b)，(e^a)^n => e^(n * a)
+    pub fn contract_exp(&self) -> Expression {
+        let expr = self.map_children(|child| child.contract_exp());
+        match &expr {
+            Expression::Multiply(_, _) => {
+                let (exps, mut others): (Vec<Expression>, Vec<Expression>) = expr
+                    .flatten_product()
+                    .into_iter()
+                    .partition(|factor| matches!(factor, Expression::Exp(_)));
+                if exps.len() < 2 {
+                    return expr;
+                }
+                let args: Vec<Expression> = exps
+                    .into_iter()
+                    .map(|factor| match factor {
+                        Expression::Exp(arg) => *arg,
+                        _ => unreachable!("partitioned on Exp"),
+                    })
+                    .collect();
+                let sum = args.into_iter().reduce(Expression::add).expect("at least two exponentials");
+                others.push(Expression::exp(sum));
+                build_product(&others)
+            }
+            Expression::Divide(left, right) => match (&**left, &**right) {
+                (Expression::Exp(a), Expression::Exp(b)) => {
+                    Expression::exp(Expression::subtract((**a).clone(), (**b).clone()))
+                }
+                _ => expr,
+            },
+            Expression::Power(base, n) => match &**base {
+                Expression::Exp(a) => Expression::exp(Expression::multiply((**n).clone(), (**a).clone())),
+                _ => expr,
+            },
+            _ => expr,
+        }
+    }
+
+    // 可选的双曲正切展开，默认的 simplify 不会调用：tanh(u) = sinh(u) / cosh(u)
+    pub fn expand_tanh(&self) -> Expression {
+        let expr = self.map_children(|child| child.expand_tanh());
+        match &expr {
+            Expression::Tanh(u) => Expression::divide(Expression::sinh((**u).clone()), Expression::cosh((**u).clone())),
+            _ => expr,
+        }
+    }
+
+    // 可选的双曲函数识别，默认的 simplify 不会调用：
+    //   (e^u + e^(-u)) / 2 = cosh(u)、(e^u - e^(-u)) / 2 = sinh(u)，乘 0.5 的写法同理；
+    //   (e^u - e^(-u)) / (e^u + e^(-u)) = tanh(u)
+    pub fn exp_to_hyperbolic(&self) -> Expression {
+        let expr = self.map_children(|child| child.exp_to_hyperbolic());
+        let recognized = match &expr {
+            Expression::Divide(left, right) => match (exp_pair(left), exp_pair(right)) {
+                (Some((false, u)), Some((true, v))) if u == v => Some(Expression::tanh(u)),
+                (pair, _) if right.constant_value() == Some(2.0) => pair.map(hyperbolic_of_pair),
+                _ => None,
+            },
+            Expression::Multiply(left, right) => match (&**left, &**right) {
+                (Expression::Constant(c), sum) | (sum, Expression::Constant(c)) if *c == 0.5 => {
+                    exp_pair(sum).map(hyperbolic_of_pair)
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        recognized.unwrap_or(expr)
+    }
+
+    // 把和式中的分式通分合并：a/b ± c/d = (a*d ± c*b)/(b*d)，a ± c/d = (a*d ± c)/d，
+    // 分母相同时直接合并分子；合并后分别化简分子和分母
+    pub fn combine_fractions(&self) -> Expression {
+        let expr = self.map_children(|child| child.combine_fractions());
+        let (left, right, sign) = match &expr {
+            Expression::Add(left, right) => (left, right, 1.0),
+            Expression::Subtract(left, right) => (left, right, -1.0),
+            _ => return expr,
+        };
+        let (a, b) = split_fraction(left);
+        let (c, d) = split_fraction(right);
+        let (numerator, denominator) = match (b, d) {
+            (None, None) => return expr,
+            (Some(b), Some(d)) if b == d => (combine(a.clone(), c.clone(), sign), b.clone()),
+            (Some(b), Some(d)) => (
+                combine(
+                    Expression::multiply(a.clone(), d.clone()),
+                    Expression::multiply(c.clone(), b.clone()),
+                    sign,
+                ),
+                Expression::multiply(b.clone(), d.clone()),
+            ),
+            (None, Some(d)) => (combine(Expression::multiply(a.clone(), d.clone()), c.clone(), sign), d.clone()),
+            (Some(b), None) => (combine(a.clone(), Expression::multiply(c.clone(), b.clone()), sign), b.clone()),
+        };
+        Expression::divide(numerator.simplify(), denominator.simplify())
+    }
+
+    // 反复调用 simplify 直到结果不再变化；规则可能来回振荡，
+    // 最多迭代 SIMPLIFY_MAX_PASSES 次后返回当前结果
+    pub fn simplify_fully(&self) -> Expression {
+        let mut current = self.simplify();
+        for _ in 1..SIMPLIFY_MAX_PASSES {
+            let next = current.simplify();
+            if next == current {
+                break;
+            }
+            current = next;
+        }
+        current
+    }
+
+    // simplify 之后把能精确写成分数的常数还原为最简分数，如 6/8 => 3/4、1/3 + 1/6 => 1/2。
+    // 结果不要再交给 simplify，否则分数又会折叠成小数
+    pub fn simplify_rational(&self) -> Expression {
+        self.simplify().map(&|expr| match expr {
+            Expression::Constant(c) => as_fraction(*c).map(|(n, d)| Expression::rational(n, d)),
+            _ => None,
+        })
+    }
+
+    // 使用默认的 SimplifyOptions。按树递归，depth() 在 100 左右以内时在 2MB 的线程栈上是安全的（未优化构建），
+    // 优化构建或 8MB 的主线程栈上可以深几倍；更深的树可能栈溢出
     pub fn simplify(&self) -> Expression {
+        self.simplify_with(&SimplifyOptions::default())
+    }
+
+    // 只应用 opts 中开启的规则组；对数和指数展开在化简之前整体做一遍，反三角化简在之后
+    pub fn simplify_with(&self, opts: &SimplifyOptions) -> Expression {
+        let simplified = match (opts.expand_logs, opts.expand_exp) {
+            (false, false) => self.simplify_node(opts),
+            (true, false) => self.expand_logs().simplify_node(opts),
+            (false, true) => self.expand_exp().simplify_node(opts),
+            (true, true) => self.expand_logs().expand_exp().simplify_node(opts),
+        };
+        if opts.inverse_trig {
+            simplified.simplify_inverse_trig()
+        } else {
+            simplified
+        }
+    }
+
+    // 左结合的运算链（a + b - c、x * y / z）沿左操作数迭代展开，逐个合并右操作数，
+    // 所以链再长也不会加深递归，只有括号、函数调用等真正的嵌套才会
+    fn simplify_node(&self, opts: &SimplifyOptions) -> Expression {
+        let mut chain = Vec::new();
+        let mut bottom = self;
+        while let Expression::Add(left, right)
+        | Expression::Subtract(left, right)
+        | Expression::Multiply(left, right)
+        | Expression::Divide(left, right) = bottom
+        {
+            chain.push((bottom, &**right));
+            bottom = left;
+        }
+
+        let mut result = bottom.simplify_operands(opts);
+        for (node, right) in chain.into_iter().rev() {
+            let right = right.simplify_node(opts);
+            result = node.combine_arithmetic(result, right, opts);
+        }
+        result
+    }
+
+    // 加减乘除节点在左右操作数都已化简之后的规则
+    fn combine_arithmetic(&self, left: Expression, right: Expression, opts: &SimplifyOptions) -> Expression {
         match self {
-            Expression::Constant(_) | Expression::Variable(_) => self.clone(),
-            Expression::Add(left, right) => {
-                let left = (**left).simplify();
-                let right = (**right).simplify();
+            Expression::Add(_, _) => {
                 match (&left, &right) {
                     // 0 + x = x
                     (Expression::Constant(c), _) if *c == 0.0 => right,
                     (_, Expression::Constant(c)) if *c == 0.0 => left,
                     // 常数合并
-                    (Expression::Constant(c1), Expression::Constant(c2)) => {
-                        Expression::constant(c1 + c2)
+                    (Expression::Constant(c1), Expression::Constant(c2)) if opts.fold_constants => {
+                        fold_constant(c1 + c2, || Expression::add(left.clone(), right.clone()))
+                    }
+                    (Expression::Constant(_), Expression::Constant(_)) => Expression::add(left, right),
+                    // 嵌套的和式展开后整体合并，平方三角项交给恒等式处理
+                    _ if !opts.collect_like_terms => Expression::add(left, right),
+                    _ if is_sum(&left) || is_sum(&right) || (opts.trig_identities && has_squared_function(&left, &right)) => {
+                        collect_sum(self_sum(&left, &right, 1.0), opts.trig_identities)
                     }
-                    // 相同项合并
-                    (Expression::Variable(v1), Expression::Variable(v2)) if v1 == v2 => {
-                        Expression::multiply(
-                            Expression::constant(2.0),
-                            Expression::variable(v1)
-                        )
+                    // 相同项合并：a*t + b*t = (a+b)*t
+                    _ => {
+                        let (c1, t1) = split_coefficient(&left);
+                        let (c2, t2) = split_coefficient(&right);
+                        if t1 == t2 {
+                            scaled(c1 + c2, t1)
+                        } else {
+                            Expression::add(left, right)
+                        }
                     }
-                    _ => Expression::add(left, right),
                 }
             }
-            Expression::Subtract(left, right) => {
-                let left = (**left).simplify();
-                let right = (**right).simplify();
+            Expression::Subtract(_, _) => {
                 match (&left, &right) {
                     // x - 0 = x
                     (_, Expression::Constant(c)) if *c == 0.0 => left,
+                    // 0 - x = -x
+                    (Expression::Constant(c), _) if *c == 0.0 => negate(&right),
                     // 常数合并
-                    (Expression::Constant(c1), Expression::Constant(c2)) => {
-                        Expression::constant(c1 - c2)
+                    (Expression::Constant(c1), Expression::Constant(c2)) if opts.fold_constants => {
+                        fold_constant(c1 - c2, || Expression::subtract(left.clone(), right.clone()))
                     }
-                    // x - x = 0
-                    (Expression::Variable(v1), Expression::Variable(v2)) if v1 == v2 => {
-                        Expression::constant(0.0)
+                    (Expression::Constant(_), Expression::Constant(_)) => Expression::subtract(left, right),
+                    _ if !opts.collect_like_terms => Expression::subtract(left, right),
+                    _ if is_sum(&left) || is_sum(&right) || (opts.trig_identities && has_squared_function(&left, &right)) => {
+                        collect_sum(self_sum(&left, &right, -1.0), opts.trig_identities)
+                    }
+                    // 相同项合并：a*t - b*t = (a-b)*t，t - t = 0
+                    _ => {
+                        let (c1, t1) = split_coefficient(&left);
+                        let (c2, t2) = split_coefficient(&right);
+                        if t1 == t2 {
+                            scaled(c1 - c2, t1)
+                        } else if is_negative(&right) {
+                            // a - (-b) = a + b
+                            Expression::add(left, negate(&right))
+                        } else {
+                            Expression::subtract(left, right)
+                        }
                     }
-                    _ => Expression::subtract(left, right),
                 }
             }
-            Expression::Multiply(left, right) => {
-                let left = (**left).simplify();
-                let right = (**right).simplify();
+            Expression::Multiply(_, _) => {
                 match (&left, &right) {
                     // 0 * x = 0
                     (Expression::Constant(c), _) | (_, Expression::Constant(c)) if *c == 0.0 => {
@@ -53,42 +399,104 @@ impl Expression {
                     // 1 * x = x
                     (Expression::Constant(c), _) if *c == 1.0 => right,
                     (_, Expression::Constant(c)) if *c == 1.0 => left,
+                    // -1 * x = -x
+                    (Expression::Constant(c), other) | (other, Expression::Constant(c)) if *c == -1.0 => negate(other),
                     // 常数合并
-                    (Expression::Constant(c1), Expression::Constant(c2)) => {
-                        Expression::constant(c1 * c2)
+                    (Expression::Constant(c1), Expression::Constant(c2)) if opts.fold_constants => {
+                        fold_constant(c1 * c2, || Expression::multiply(left.clone(), right.clone()))
                     }
-                    // 同类项合并
-                    (Expression::Variable(v1), Expression::Variable(v2)) if v1 == v2 => {
-                        Expression::power(
-                            Expression::variable(v1),
-                            Expression::constant(2.0)
-                        )
+                    (Expression::Constant(_), Expression::Constant(_)) => Expression::multiply(left, right),
+                    _ if !opts.collect_like_terms => Expression::multiply(left, right),
+                    // 分式并成一个除法，交给 Divide 约分：(a/b)*(c/d) = (a*c)/(b*d)，(a/b)*c = (a*c)/b
+                    (Expression::Divide(a, b), Expression::Divide(c, d)) => Expression::divide(
+                        Expression::multiply((**a).clone(), (**c).clone()),
+                        Expression::multiply((**b).clone(), (**d).clone()),
+                    )
+                    .simplify_node(opts),
+                    (Expression::Divide(a, b), other) | (other, Expression::Divide(a, b)) => {
+                        Expression::divide(Expression::multiply(other.clone(), (**a).clone()), (**b).clone()).simplify_node(opts)
+                    }
+                    // 嵌套的乘积展开后整体合并，负号并入系数：x * -y = -(x * y)
+                    (Expression::Multiply(_, _) | Expression::Negate(_), _) | (_, Expression::Multiply(_, _) | Expression::Negate(_)) => {
+                        let mut factors = left.flatten_product();
+                        factors.extend(right.flatten_product());
+                        collect_product(factors)
+                    }
+                    // 同底数幂相乘：a*x^m * b*x^n = (a*b)*x^(m+n)
+                    _ => {
+                        let (c1, t1) = split_coefficient(&left);
+                        let (c2, t2) = split_coefficient(&right);
+                        let (b1, e1) = split_power(&t1);
+                        let (b2, e2) = split_power(&t2);
+                        if b1 == b2 {
+                            scaled(c1 * c2, Expression::power(b1, Expression::add(e1, e2)).simplify_node(opts))
+                        } else {
+                            Expression::multiply(left, right)
+                        }
                     }
-                    _ => Expression::multiply(left, right),
                 }
             }
-            Expression::Divide(left, right) => {
-                let left = (**left).simplify();
-                let right = (**right).simplify();
+            Expression::Divide(_, _) => {
                 match (&left, &right) {
-                    // 0 / x = 0
-                    (Expression::Constant(c), _) if *c == 0.0 => Expression::constant(0.0),
+                    // 0 / x = 0 (x ≠ 0)
+                    (Expression::Constant(c), _) if *c == 0.0 && right.constant_value() != Some(0.0) => {
+                        Expression::constant(0.0)
+                    }
                     // x / 1 = x
                     (_, Expression::Constant(c)) if *c == 1.0 => left,
+                    // x / 0 保持原样，不做任何约分
+                    (_, Expression::Constant(c)) if *c == 0.0 => Expression::divide(left, right),
                     // 常数合并
-                    (Expression::Constant(c1), Expression::Constant(c2)) if *c2 != 0.0 => {
-                        Expression::constant(c1 / c2)
+                    (Expression::Constant(c1), Expression::Constant(c2)) if *c2 != 0.0 && opts.fold_constants => {
+                        fold_constant(c1 / c2, || Expression::divide(left.clone(), right.clone()))
+                    }
+                    (Expression::Constant(_), Expression::Constant(_)) => Expression::divide(left, right),
+                    _ if !opts.collect_like_terms => Expression::divide(left, right),
+                    // 嵌套的分式：(a/b)/(c/d) = (a*d)/(b*c)，(a/b)/c = a/(b*c)，a/(b/c) = (a*c)/b
+                    (Expression::Divide(a, b), Expression::Divide(c, d)) => Expression::divide(
+                        Expression::multiply((**a).clone(), (**d).clone()),
+                        Expression::multiply((**b).clone(), (**c).clone()),
+                    )
+                    .simplify_node(opts),
+                    (Expression::Divide(a, b), _) => {
+                        Expression::divide((**a).clone(), Expression::multiply((**b).clone(), right.clone())).simplify_node(opts)
                     }
-                    // x / x = 1
-                    (Expression::Variable(v1), Expression::Variable(v2)) if v1 == v2 => {
-                        Expression::constant(1.0)
+                    (_, Expression::Divide(b, c)) => {
+                        Expression::divide(Expression::multiply(left.clone(), (**c).clone()), (**b).clone()).simplify_node(opts)
+                    }
+                    // 同底数幂相除：a*x^m / (b*x^n) = a*x^(m-n) / b，x / x = 1
+                    _ => {
+                        let (c1, t1) = split_coefficient(&left);
+                        let (c2, t2) = split_coefficient(&right);
+                        let (b1, e1) = split_power(&t1);
+                        let (b2, e2) = split_power(&t2);
+                        if b1 == b2 {
+                            let quotient = Expression::power(b1, Expression::subtract(e1, e2)).simplify_node(opts);
+                            match quotient.constant_value() {
+                                Some(q) => Expression::constant(c1 * q / c2),
+                                _ if c2 == 1.0 => scaled(c1, quotient),
+                                _ => Expression::divide(scaled(c1, quotient), Expression::constant(c2)),
+                            }
+                        } else {
+                            cancel_common_factors(&left, &right).unwrap_or_else(|| Expression::divide(left, right))
+                        }
                     }
-                    _ => Expression::divide(left, right),
                 }
             }
+            _ => unreachable!("only arithmetic nodes form chains"),
+        }
+    }
+
+    // 加减乘除以外的节点：先化简子表达式，再应用该节点的规则
+    fn simplify_operands(&self, opts: &SimplifyOptions) -> Expression {
+        match self {
+            Expression::Constant(_) | Expression::Variable(_) => self.clone(),
+            Expression::Add(..) | Expression::Subtract(..) | Expression::Multiply(..) | Expression::Divide(..) => {
+                unreachable!("simplify_node handles arithmetic chains")
+            }
             Expression::Power(base, exponent) => {
-                let base = (**base).simplify();
-                let exponent = (**exponent).simplify();
+                let base = (**base).simplify_node(opts);
+                let exponent = (**exponent).simplify_node(opts);
                 match (&base, &exponent) {
                     // x^0 = 1
                     (_, Expression::Constant(c)) if *c == 0.0 => Expression::constant(1.0),
@@ -101,15 +509,36 @@ impl Expression {
                     // 1^n = 1
                     (Expression::Constant(c), _) if *c == 1.0 => Expression::constant(1.0),
                     // 常数合并
-                    (Expression::Constant(c), Expression::Constant(n)) => {
-                        Expression::constant(c.powf(*n))
+                    (Expression::Constant(c), Expression::Constant(n)) if opts.fold_constants => {
+                        fold_constant(c.powf(*n), || Expression::power(base.clone(), exponent.clone()))
+                    }
+                    // (x^a)^n = x^(a*n)，只在外层指数为整数时成立：(x^2)^0.5 = |x| 而不是 x
+                    (Expression::Power(inner, a), Expression::Constant(n)) if n.fract() == 0.0 => {
+                        Expression::power(
+                            (**inner).clone(),
+                            Expression::multiply(exponent.clone(), (**a).clone()).simplify_node(opts)
+                        ).simplify_node(opts)
                     }
                     _ => Expression::power(base, exponent),
                 }
             }
+            Expression::Sqrt(expr) => {
+                let simplified = expr.simplify_node(opts);
+                match simplified {
+                    // 只折叠完全平方数，sqrt(2) 等保持符号形式
+                    Expression::Constant(x) if x >= 0.0 && x.sqrt().fract() == 0.0 && opts.fold_constants => {
+                        Expression::constant(x.sqrt())
+                    }
+                    // sqrt(u^2) = |u|
+                    Expression::Power(base, exponent) if *exponent == Expression::Constant(2.0) => {
+                        Expression::abs(*base).simplify_node(opts)
+                    }
+                    _ => Expression::sqrt(simplified)
+                }
+            }
             Expression::Root(base, n) => {
-                let base = (**base).simplify();
-                let n = (**n).simplify();
+                let base = (**base).simplify_node(opts);
+                let n = (**n).simplify_node(opts);
                 // 转换为幂函数处理
                 Expression::power(
                     base,
@@ -117,10 +546,10 @@ impl Expression {
                         Expression::constant(1.0),
                         n
                     )
-                ).simplify()
+                ).simplify_node(opts)
             }
             Expression::Sin(expr) => {
-                let simplified = expr.simplify();
+                let simplified = expr.simplify_node(opts);
                 match simplified {
                     Expression::Constant(x) => {
                         if x == 0.0 { Expression::constant(0.0) }  // sin(0) = 0
@@ -130,7 +559,7 @@ impl Expression {
                 }
             }
             Expression::Cos(expr) => {
-                let simplified = expr.simplify();
+                let simplified = expr.simplify_node(opts);
                 match simplified {
                     Expression::Constant(x) => {
                         if x == 0.0 { Expression::constant(1.0) }  // cos(0) = 1
@@ -140,7 +569,7 @@ impl Expression {
                 }
             }
             Expression::Tan(expr) => {
-                let simplified = expr.simplify();
+                let simplified = expr.simplify_node(opts);
                 match simplified {
                     Expression::Constant(x) => {
                         if x == 0.0 { Expression::constant(0.0) }  // tan(0) = 0
@@ -149,8 +578,20 @@ impl Expression {
                     _ => Expression::tan(simplified)
                 }
             }
+            Expression::Sec(expr) => {
+                let simplified = expr.simplify_node(opts);
+                match simplified {
+                    Expression::Constant(x) => {
+                        if x == 0.0 { Expression::constant(1.0) }  // sec(0) = 1
+                        else { Expression::sec(simplified) }
+                    }
+                    _ => Expression::sec(simplified)
+                }
+            }
+            Expression::Csc(expr) => Expression::csc(expr.simplify_node(opts)),
+            Expression::Cot(expr) => Expression::cot(expr.simplify_node(opts)),
             Expression::Arcsin(expr) => {
-                let simplified = expr.simplify();
+                let simplified = expr.simplify_node(opts);
                 match simplified {
                     Expression::Constant(x) => {
                         if x == 0.0 { Expression::constant(0.0) }  // arcsin(0) = 0
@@ -162,7 +603,7 @@ impl Expression {
                 }
             }
             Expression::Arccos(expr) => {
-                let simplified = expr.simplify();
+                let simplified = expr.simplify_node(opts);
                 match simplified {
                     Expression::Constant(x) => {
                         if x == 1.0 { Expression::constant(0.0) }  // arccos(1) = 0
@@ -174,7 +615,7 @@ impl Expression {
                 }
             }
             Expression::Arctan(expr) => {
-                let simplified = expr.simplify();
+                let simplified = expr.simplify_node(opts);
                 match simplified {
                     Expression::Constant(x) => {
                         if x == 0.0 { Expression::constant(0.0) }  // arctan(0) = 0
@@ -186,32 +627,32 @@ impl Expression {
                 }
             }
             Expression::Exp(expr) => {
-                let simplified = expr.simplify();
+                let simplified = expr.simplify_node(opts);
                 match simplified {
                     Expression::Constant(x) => {
                         if x == 0.0 { Expression::constant(1.0) }  // e^0 = 1
                         else if x == 1.0 { Expression::constant(std::f64::consts::E) }  // e^1 = e
                         else { Expression::exp(simplified) }
                     }
-                    Expression::Ln(inner) => inner.simplify(),  // e^(ln(x)) = x
+                    Expression::Ln(inner) => inner.simplify_node(opts),  // e^(ln(x)) = x
                     _ => Expression::exp(simplified)
                 }
             }
             Expression::Ln(expr) => {
-                let simplified = expr.simplify();
+                let simplified = expr.simplify_node(opts);
                 match simplified {
                     Expression::Constant(x) => {
                         if x == 1.0 { Expression::constant(0.0) }  // ln(1) = 0
                         else if x == std::f64::consts::E { Expression::constant(1.0) }  // ln(e) = 1
                         else { Expression::ln(simplified) }
                     }
-                    Expression::Exp(inner) => inner.simplify(),  // ln(e^x) = x
+                    Expression::Exp(inner) => inner.simplify_node(opts),  // ln(e^x) = x
                     _ => Expression::ln(simplified)
                 }
             }
             Expression::Log(base, expr) => {
-                let simplified_base = base.simplify();
-                let simplified_expr = expr.simplify();
+                let simplified_base = base.simplify_node(opts);
+                let simplified_expr = expr.simplify_node(opts);
                 match (simplified_base, simplified_expr) {
                     (Expression::Constant(b), Expression::Constant(x)) => {
                         if x == 1.0 { Expression::constant(0.0) }  // log_b(1) = 0
@@ -222,7 +663,7 @@ impl Expression {
                 }
             }
             Expression::Sinh(expr) => {
-                let simplified = expr.simplify();
+                let simplified = expr.simplify_node(opts);
                 match simplified {
                     Expression::Constant(x) => {
                         if x == 0.0 { Expression::constant(0.0) }  // sinh(0) = 0
@@ -232,7 +673,7 @@ impl Expression {
                 }
             }
             Expression::Cosh(expr) => {
-                let simplified = expr.simplify();
+                let simplified = expr.simplify_node(opts);
                 match simplified {
                     Expression::Constant(x) => {
                         if x == 0.0 { Expression::constant(1.0) }  // cosh(0) = 1
@@ -242,7 +683,7 @@ impl Expression {
                 }
             }
             Expression::Tanh(expr) => {
-                let simplified = expr.simplify();
+                let simplified = expr.simplify_node(opts);
                 match simplified {
                     Expression::Constant(x) => {
                         if x == 0.0 { Expression::constant(0.0) }  // tanh(0) = 0
@@ -251,6 +692,599 @@ impl Expression {
                     _ => Expression::tanh(simplified)
                 }
             }
+            Expression::Arctanh(expr) => {
+                let simplified = expr.simplify_node(opts);
+                match simplified {
+                    Expression::Constant(x) => {
+                        if x == 0.0 { Expression::constant(0.0) }  // arctanh(0) = 0
+                        else { Expression::arctanh(simplified) }
+                    }
+                    _ => Expression::arctanh(simplified)
+                }
+            }
+            Expression::Factorial(expr) => {
+                let simplified = expr.simplify_node(opts);
+                match simplified {
+                    // 非负整数直接折叠：5! = 120；171! 已超出 f64 的范围，更大的参数保持符号形式
+                    Expression::Constant(n) if (0.0..=170.0).contains(&n) && n.fract() == 0.0 && opts.fold_constants => {
+                        let value = (1..=n as u64).map(|k| k as f64).product();
+                        fold_constant(value, || Expression::factorial(simplified.clone()))
+                    }
+                    _ => Expression::factorial(simplified)
+                }
+            }
+            Expression::Polygamma(order, expr) => Expression::polygamma(order.simplify_node(opts), expr.simplify_node(opts)),
+            Expression::Negate(expr) => {
+                let simplified = expr.simplify_node(opts);
+                match simplified {
+                    Expression::Constant(c) if opts.fold_constants => Expression::constant(-c),
+                    Expression::Constant(_) => Expression::negate(simplified),
+                    // -(-x) = x
+                    Expression::Negate(inner) => *inner,
+                    // -(c * u) = (-c) * u，其余保持 Negate
+                    _ => negate(&simplified),
+                }
+            }
+            Expression::Abs(expr) => {
+                let simplified = expr.simplify_node(opts);
+                match simplified {
+                    Expression::Constant(x) if opts.fold_constants => Expression::constant(x.abs()),
+                    // ||x|| = |x|
+                    Expression::Abs(_) => simplified,
+                    _ => Expression::abs(simplified)
+                }
+            }
+            Expression::Min(left, right) | Expression::Max(left, right) => {
+                let is_min = matches!(self, Expression::Min(_, _));
+                let left = left.simplify_node(opts);
+                let right = right.simplify_node(opts);
+                match (&left, &right) {
+                    (Expression::Constant(a), Expression::Constant(b)) if opts.fold_constants => {
+                        Expression::constant(if is_min { a.min(*b) } else { a.max(*b) })
+                    }
+                    _ if left == right => left,  // min(u, u) = u
+                    _ if is_min => Expression::min(left, right),
+                    _ => Expression::max(left, right),
+                }
+            }
+            Expression::Heaviside(expr) => {
+                let simplified = expr.simplify_node(opts);
+                match simplified {
+                    Expression::Constant(x) if opts.fold_constants => {
+                        Expression::constant(if x >= 0.0 { 1.0 } else { 0.0 })
+                    }
+                    _ => Expression::heaviside(simplified),
+                }
+            }
+            Expression::Clamp(expr, lo, hi) => {
+                let expr = expr.simplify_node(opts);
+                let lo = lo.simplify_node(opts);
+                let hi = hi.simplify_node(opts);
+                match (&expr, &lo, &hi) {
+                    (Expression::Constant(x), Expression::Constant(lo), Expression::Constant(hi)) if opts.fold_constants => {
+                        Expression::constant(x.max(*lo).min(*hi))
+                    }
+                    _ => Expression::clamp(expr, lo, hi),
+                }
+            }
+            Expression::Piecewise(condition, if_nonnegative, otherwise) => {
+                let condition = condition.simplify_node(opts);
+                let if_nonnegative = if_nonnegative.simplify_node(opts);
+                let otherwise = otherwise.simplify_node(opts);
+                match condition {
+                    Expression::Constant(c) if c >= 0.0 => if_nonnegative,
+                    Expression::Constant(_) => otherwise,
+                    _ if if_nonnegative == otherwise => otherwise,
+                    _ => Expression::piecewise(condition, if_nonnegative, otherwise),
+                }
+            }
+        }
+    }
+}
+
+// 对数的 (底数, 真数)；自然对数的底数为 None
+fn log_parts(expr: &Expression) -> Option<(Option<&Expression>, &Expression)> {
+    match expr {
+        Expression::Ln(arg) => Some((None, arg)),
+        Expression::Log(base, arg) => Some((Some(base), arg)),
+        _ => None,
+    }
+}
+
+fn make_log(base: Option<&Expression>, arg: Expression) -> Expression {
+    match base {
+        Some(base) => Expression::log(base.clone(), arg),
+        None => Expression::ln(arg),
+    }
+}
+
+fn expand_log(base: Option<&Expression>, arg: &Expression) -> Expression {
+    match arg {
+        Expression::Multiply(a, b) => Expression::add(expand_log(base, a), expand_log(base, b)),
+        Expression::Divide(a, b) => Expression::subtract(expand_log(base, a), expand_log(base, b)),
+        Expression::Power(a, n) => Expression::multiply((**n).clone(), expand_log(base, a)),
+        Expression::Root(a, n) => Expression::divide(expand_log(base, a), (**n).clone()),
+        _ => make_log(base, arg.clone()),
+    }
+}
+
+fn expand_exp_of(arg: &Expression) -> Expression {
+    match arg {
+        Expression::Add(a, b) => Expression::multiply(expand_exp_of(a), expand_exp_of(b)),
+        Expression::Subtract(a, b) => Expression::divide(expand_exp_of(a), expand_exp_of(b)),
+        Expression::Multiply(left, right) => match (&**left, &**right) {
+            (Expression::Constant(n), u) | (u, Expression::Constant(n)) => {
+                Expression::power(expand_exp_of(u), Expression::constant(*n))
+            }
+            _ => Expression::exp(arg.clone()),
+        },
+        Expression::Ln(x) => (**x).clone(),
+        _ => Expression::exp(arg.clone()),
+    }
+}
+
+// 识别 e^u ± e^(-u)：返回是否为和以及 u，差式取被减数的指数作为 u
+fn exp_pair(expr: &Expression) -> Option<(bool, Expression)> {
+    let (left, right, is_sum) = match expr {
+        Expression::Add(left, right) => (left, right, true),
+        Expression::Subtract(left, right) => (left, right, false),
+        _ => return None,
+    };
+    match (&**left, &**right) {
+        (Expression::Exp(p), Expression::Exp(q)) => {
+            let opposite = Expression::add((**p).clone(), (**q).clone()).simplify().constant_value() == Some(0.0);
+            if !opposite {
+                return None;
+            }
+            // 和式里 e^(-u) 可能在前，统一取不带负号的一侧作为 u
+            let u = if is_sum && is_negative(&p.simplify()) { q } else { p };
+            Some((is_sum, u.simplify()))
+        }
+        _ => None,
+    }
+}
+
+fn hyperbolic_of_pair((is_sum, u): (bool, Expression)) -> Expression {
+    if is_sum { Expression::cosh(u) } else { Expression::sinh(u) }
+}
+
+// 分式拆成 (分子, Some(分母))，其余表达式分母为 None
+fn split_fraction(expr: &Expression) -> (&Expression, Option<&Expression>) {
+    match expr {
+        Expression::Divide(numerator, denominator) => (numerator, Some(denominator)),
+        _ => (expr, None),
+    }
+}
+
+fn combine(left: Expression, right: Expression, sign: f64) -> Expression {
+    if sign < 0.0 {
+        Expression::subtract(left, right)
+    } else {
+        Expression::add(left, right)
+    }
+}
+
+fn is_sum(expr: &Expression) -> bool {
+    match expr {
+        Expression::Add(_, _) | Expression::Subtract(_, _) => true,
+        Expression::Negate(inner) => is_sum(inner),
+        _ => false,
+    }
+}
+
+fn self_sum(left: &Expression, right: &Expression, sign: f64) -> Vec<Expression> {
+    let mut terms = left.flatten_sum();
+    if sign < 0.0 {
+        terms.extend(right.flatten_sum().iter().map(negate));
+    } else {
+        terms.extend(right.flatten_sum());
+    }
+    terms
+}
+
+fn negate(term: &Expression) -> Expression {
+    match term.constant_value() {
+        Some(c) => Expression::constant(-c),
+        None => {
+            let (c, t) = split_coefficient(term);
+            scaled(-c, t)
+        }
+    }
+}
+
+fn is_negative(term: &Expression) -> bool {
+    match term.constant_value() {
+        Some(c) => c < 0.0,
+        None => split_coefficient(term).0 < 0.0,
+    }
+}
+
+// 合并加数列表中的常数与同类项，常数项放在最后
+fn collect_sum(terms: Vec<Expression>, identities: bool) -> Expression {
+    let mut constant = 0.0;
+    let mut collected: Vec<(f64, Expression)> = Vec::new();
+    for term in terms {
+        if let Some(c) = term.constant_value() {
+            constant += c;
+            continue;
+        }
+        let (c, t) = split_coefficient(&term);
+        match collected.iter_mut().find(|(_, existing)| *existing == t) {
+            Some((total, _)) => *total += c,
+            None => collected.push((c, t)),
+        }
+    }
+
+    if identities {
+        apply_pythagorean_identities(&mut collected, &mut constant);
+    }
+
+    let mut terms: Vec<Expression> = collected
+        .into_iter()
+        .filter(|(c, _)| *c != 0.0)
+        .map(|(c, t)| scaled(c, t))
+        .collect();
+    if constant != 0.0 {
+        terms.push(Expression::constant(constant));
+    }
+    build_sum(&terms)
+}
+
+// 若项形如 f(u)^2，返回 f(u)
+fn squared_function(term: &Expression) -> Option<&Expression> {
+    match term {
+        Expression::Power(base, exponent) if exponent.constant_value() == Some(2.0) => match **base {
+            Expression::Sin(_) | Expression::Cos(_) | Expression::Tan(_) |
+            Expression::Sinh(_) | Expression::Cosh(_) => Some(&**base),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn has_squared_function(left: &Expression, right: &Expression) -> bool {
+    squared_function(&split_coefficient(left).1).is_some() ||
+        squared_function(&split_coefficient(right).1).is_some()
+}
+
+// c*sin²u + c*cos²u = c，c*cosh²u - c*sinh²u = c，c + c*tan²u = c*sec²u，c + c*sinh²u = c*cosh²u
+fn apply_pythagorean_identities(collected: &mut Vec<(f64, Expression)>, constant: &mut f64) {
+    let mut i = 0;
+    while i < collected.len() {
+        let (c, term) = collected[i].clone();
+        let partner = match squared_function(&term) {
+            Some(Expression::Sin(u)) => Some((Expression::cos((**u).clone()), c)),
+            Some(Expression::Cosh(u)) => Some((Expression::sinh((**u).clone()), -c)),
+            _ => None,
+        };
+
+        if let Some((function, partner_coeff)) = partner {
+            let target = Expression::power(function, Expression::constant(2.0));
+            if let Some(j) = collected.iter().position(|(cj, t)| *t == target && *cj == partner_coeff) {
+                *constant += c;
+                collected.remove(i.max(j));
+                collected.remove(i.min(j));
+                continue;
+            }
+        }
+
+        let combined = match squared_function(&term) {
+            Some(Expression::Tan(u)) => Some(Expression::sec((**u).clone())),
+            Some(Expression::Sinh(u)) => Some(Expression::cosh((**u).clone())),
+            _ => None,
+        };
+        if let Some(function) = combined {
+            if *constant == c {
+                collected[i].1 = Expression::power(function, Expression::constant(2.0));
+                *constant = 0.0;
+            }
+        }
+        i += 1;
+    }
+}
+
+// 合并因子列表中的常数与同底数幂，系数放在最前
+fn collect_product(factors: Vec<Expression>) -> Expression {
+    let mut coeff = 1.0;
+    let mut collected: Vec<(Expression, Expression)> = Vec::new();
+    for factor in factors {
+        if let Some(c) = factor.constant_value() {
+            coeff *= c;
+            continue;
+        }
+        let (base, exponent) = split_power(&factor);
+        match collected.iter_mut().find(|(existing, _)| *existing == base) {
+            Some((_, total)) => *total = Expression::add(total.clone(), exponent).simplify(),
+            None => collected.push((base, exponent)),
+        }
+    }
+
+    let mut factors = Vec::new();
+    for (base, exponent) in collected {
+        let factor = Expression::power(base, exponent).simplify();
+        match factor.constant_value() {
+            Some(c) => coeff *= c,
+            None => factors.push(factor),
+        }
+    }
+    if coeff == 0.0 || factors.is_empty() {
+        return Expression::constant(coeff);
+    }
+    scaled(coeff, build_product(&factors))
+}
+
+// 约去分子分母乘积中相同底数的因子（按结构相等比较）以及整数系数的公约数，
+// 如 (a*b)/b = a、x^2*y/(x*y) = x、6*x/4 = 3*x/2；没有可约的因子时返回 None
+fn cancel_common_factors(numerator: &Expression, denominator: &Expression) -> Option<Expression> {
+    fn factors(term: &Expression) -> Vec<(Expression, Expression)> {
+        term.flatten_product()
+            .iter()
+            .filter(|factor| factor.constant_value() != Some(1.0))
+            .map(split_power)
+            .collect()
+    }
+
+    let (mut c1, t1) = numerator.as_coeff_term();
+    let (mut c2, t2) = denominator.as_coeff_term();
+    let mut upper = factors(&t1);
+    let mut lower = factors(&t2);
+
+    let mut cancelled = false;
+    for (base, exponent) in upper.iter_mut() {
+        let Some(index) = lower.iter().position(|(other, _)| other == base) else { continue };
+        cancelled = true;
+        let (_, other) = lower.remove(index);
+        let difference = Expression::subtract(exponent.clone(), other).simplify();
+        match difference.constant_value() {
+            Some(d) if d < 0.0 => {
+                lower.push((base.clone(), Expression::constant(-d)));
+                *exponent = Expression::constant(0.0);
+            }
+            _ => *exponent = difference,
+        }
+    }
+
+    let integral = |c: f64| c.fract() == 0.0 && c.abs() < 2f64.powi(53);
+    if integral(c1) && integral(c2) {
+        let divisor = gcd(c1.abs() as u64, c2.abs() as u64);
+        if divisor > 1 {
+            cancelled = true;
+            c1 /= divisor as f64;
+            c2 /= divisor as f64;
+        }
+    }
+    if !cancelled && c2 >= 0.0 {
+        return None;
+    }
+
+    let rebuild = |coeff: f64, factors: Vec<(Expression, Expression)>| {
+        let factors: Vec<Expression> = factors
+            .into_iter()
+            .map(|(base, exponent)| Expression::power(base, exponent).simplify())
+            .filter(|factor| factor.constant_value() != Some(1.0))
+            .collect();
+        if factors.is_empty() {
+            Expression::constant(coeff)
+        } else {
+            scaled(coeff, build_product(&factors))
+        }
+    };
+    let (c1, c2) = if c2 < 0.0 { (-c1, -c2) } else { (c1, c2) };
+    let numerator = rebuild(c1, upper);
+    let denominator = rebuild(c2, lower);
+    match denominator.constant_value() {
+        Some(1.0) => Some(numerator),
+        _ => Some(Expression::divide(numerator, denominator)),
+    }
+}
+
+// 由加数列表重建和式，与解析器一样左结合，负系数项改写为减法；
+// 空列表为 0，单个加数直接返回
+pub(crate) fn build_sum(terms: &[Expression]) -> Expression {
+    match terms.split_first() {
+        None => Expression::constant(0.0),
+        Some((first, rest)) => rest.iter().fold(first.clone(), |acc, term| {
+            if is_negative(term) {
+                Expression::subtract(acc, negate(term))
+            } else {
+                Expression::add(acc, term.clone())
+            }
+        }),
+    }
+}
+
+// 由因子列表重建平衡的二叉树；空列表为 1，单个因子直接返回
+pub(crate) fn build_product(factors: &[Expression]) -> Expression {
+    match factors {
+        [] => Expression::constant(1.0),
+        [factor] => factor.clone(),
+        _ => {
+            let (left, right) = factors.split_at(factors.len() / 2);
+            Expression::multiply(build_product(left), build_product(right))
+        }
+    }
+}
+
+// 把 c*t、t*c 或 -t 拆成 (系数, 项)，其余表达式系数为 1
+fn split_coefficient(expr: &Expression) -> (f64, Expression) {
+    match expr {
+        Expression::Negate(inner) => {
+            let (c, term) = split_coefficient(inner);
+            (-c, term)
+        }
+        Expression::Multiply(left, right) => match (&**left, &**right) {
+            (Expression::Constant(c), term) | (term, Expression::Constant(c)) => (*c, term.clone()),
+            _ => (1.0, expr.clone()),
+        },
+        _ => (1.0, expr.clone()),
+    }
+}
+
+// 常数折叠得到 NaN 或无穷大时（如 0^(-1)）保留原来的符号形式，避免污染整个结果
+fn fold_constant<F>(value: f64, original: F) -> Expression
+where
+    F: FnOnce() -> Expression,
+{
+    if value.is_finite() {
+        Expression::constant(value)
+    } else {
+        original()
+    }
+}
+
+// 把 b^e 拆成 (底数, 指数)，其余表达式视为一次幂
+fn split_power(expr: &Expression) -> (Expression, Expression) {
+    match expr {
+        Expression::Power(base, exponent) => ((**base).clone(), (**exponent).clone()),
+        _ => (expr.clone(), Expression::constant(1.0)),
+    }
+}
+
+// 由系数和项重建表达式：系数为 0 得 0，系数为 1 直接返回项本身，不再包一层乘法，系数为 -1 时取负
+pub(crate) fn scaled(coeff: f64, term: Expression) -> Expression {
+    if coeff == 0.0 {
+        Expression::constant(0.0)
+    } else if coeff == 1.0 {
+        term
+    } else if coeff == -1.0 {
+        Expression::negate(term)
+    } else {
+        Expression::multiply(Expression::constant(coeff), term)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SimplifyOptions;
+    use crate::{parse, Expression};
+    use std::collections::HashMap;
+
+    fn simplified(input: &str) -> String {
+        parse(input).unwrap().simplify().to_string()
+    }
+
+    #[test]
+    fn sqrt_of_square_is_absolute_value() {
+        assert_eq!(simplified("sqrt(x^2)"), "abs(x)");
+        assert_eq!(simplified("sqrt(16)"), "4");
+
+        let bindings = HashMap::from([("x".to_string(), -3.0)]);
+        let value = parse("sqrt(x^2)").unwrap().simplify().evaluate(&bindings).unwrap();
+        assert_eq!(value, 3.0);
+    }
+
+    #[test]
+    fn factorial_folds_only_within_f64_range() {
+        assert_eq!(simplified("5!"), "120");
+        assert_eq!(simplified("0!"), "1");
+        assert_eq!(simplified("171!"), "171!");
+        assert_eq!(simplified("1000000000000000!"), "1000000000000000!");
+        assert_eq!(simplified("2.5!"), "2.5!");
+    }
+
+    #[test]
+    fn negation_stays_a_negate_node() {
+        let x = Expression::variable("x");
+        assert_eq!(parse("-x").unwrap().simplify(), Expression::negate(x.clone()));
+        assert_eq!(parse("x * -1").unwrap().simplify(), Expression::negate(x.clone()));
+        assert_eq!(parse("-(-x)").unwrap().simplify(), x);
+        assert_eq!(simplified("-(2*x)"), "-2 * x");
+        assert_eq!(simplified("-x * -y"), "x * y");
+        assert_eq!(simplified("-(x + 1) + x"), "-1");
+        assert_eq!(simplified("x - -y"), "x + y");
+        assert_eq!(parse("0 - x").unwrap().simplify(), Expression::negate(x.clone()));
+        assert_eq!(simplified("0 - 2*x"), "-2 * x");
+        assert_eq!(simplified("0 - (x + 1)"), "-(x + 1)");
+    }
+
+    #[test]
+    fn fold_constants_off_keeps_every_constant_operation() {
+        let opts = SimplifyOptions { fold_constants: false, ..SimplifyOptions::default() };
+        for input in ["abs(-2)", "min(2, 3)", "max(2, 3)", "2 + 3", "5!"] {
+            let expr = parse(input).unwrap();
+            assert_eq!(expr.simplify_with(&opts), expr, "{}", input);
         }
+        let negated = Expression::negate(Expression::constant(3.0));
+        assert_eq!(negated.simplify_with(&opts), negated);
+        assert_eq!(negated.simplify(), Expression::constant(-3.0));
+    }
+
+    #[test]
+    fn cancelled_collection_leaves_the_bare_operand() {
+        let x = Expression::variable("x");
+        assert_eq!(parse("2*x - x").unwrap().simplify(), x);
+        assert_eq!(parse("x + 2*x - 2*x").unwrap().simplify(), x);
+        assert_eq!(parse("x * y / y").unwrap().simplify(), x);
+        assert_eq!(parse("x + 2*x - 3*x + y").unwrap().simplify(), Expression::variable("y"));
+    }
+
+    #[test]
+    fn inverse_trig_compositions_are_opt_in() {
+        let inverse = |input: &str| parse(input).unwrap().simplify_inverse_trig().to_string();
+        for input in ["arctan(tan(x))", "arcsin(sin(x))", "arccos(cos(x))", "tan(arctan(x))", "sin(arcsin(x))", "cos(arccos(x))"] {
+            assert_eq!(inverse(input), "x", "{}", input);
+        }
+        assert_eq!(inverse("1 + arcsin(sin(2*x))"), "1 + 2 * x");
+        assert_eq!(inverse("arctan(sin(x))"), "arctan(sin(x))");
+        assert_eq!(simplified("arctan(tan(x))"), "arctan(tan(x))");
+    }
+
+    #[test]
+    fn hyperbolic_pythagorean_identity() {
+        assert_eq!(simplified("cosh(x)^2 - sinh(x)^2"), "1");
+        assert_eq!(simplified("3*cosh(2*x)^2 - 3*sinh(2*x)^2"), "3");
+        assert_eq!(simplified("1 + sinh(x)^2"), "cosh(x)^2");
+        assert_eq!(simplified("cosh(x)^2 - sinh(y)^2"), "cosh(x)^2 - sinh(y)^2");
+        let opts = SimplifyOptions { trig_identities: false, ..SimplifyOptions::default() };
+        assert_eq!(parse("cosh(x)^2 - sinh(x)^2").unwrap().simplify_with(&opts).to_string(), "cosh(x)^2 - sinh(x)^2");
+    }
+
+    #[test]
+    fn tanh_expansion_is_opt_in() {
+        let expanded = |input: &str| parse(input).unwrap().expand_tanh().to_string();
+        assert_eq!(expanded("tanh(x)"), "sinh(x) / cosh(x)");
+        assert_eq!(expanded("1 + tanh(2*x)"), "1 + sinh(2 * x) / cosh(2 * x)");
+        assert_eq!(expanded("sinh(x)"), "sinh(x)");
+        assert_eq!(simplified("tanh(x)"), "tanh(x)");
+    }
+
+    #[test]
+    fn exp_to_hyperbolic_is_opt_in() {
+        let hyperbolic = |input: &str| parse(input).unwrap().exp_to_hyperbolic().to_string();
+        assert_eq!(hyperbolic("(exp(x) + exp(-x))/2"), "cosh(x)");
+        assert_eq!(hyperbolic("(exp(x) - exp(-x))/2"), "sinh(x)");
+        assert_eq!(hyperbolic("0.5*(exp(-x) + exp(x))"), "cosh(x)");
+        assert_eq!(hyperbolic("(exp(2*x) - exp(-2*x))/(exp(2*x) + exp(-2*x))"), "tanh(2 * x)");
+        assert_eq!(hyperbolic("(exp(x) + exp(-y))/2"), "(exp(x) + exp(-y)) / 2");
+        assert_eq!(simplified("(exp(x) + exp(-x))/2"), "(exp(x) + exp(-x)) / 2");
+    }
+
+    #[test]
+    fn non_finite_folds_stay_symbolic() {
+        assert_eq!(simplified("x + 0^(-1)"), "x + 0^-1");
+        assert_eq!(simplified("ln(0) * x"), "ln(0) * x");
+        assert_eq!(simplified("sqrt(-1) + 1"), "sqrt(-1) + 1");
+        assert_eq!(simplified("1/0"), "1 / 0");
+        assert_eq!(simplified("x + 2^(-1)"), "x + 0.5");
+        assert!(!simplified("x + 0^(-1) + ln(0)").contains("NaN"));
+    }
+
+    #[test]
+    fn combine_fractions_uses_a_common_denominator() {
+        let combined = |input: &str| parse(input).unwrap().combine_fractions().to_string();
+        assert_eq!(combined("1/x + 1/(x+1)"), "(2 * x + 1) / (x * (x + 1))");
+        assert_eq!(combined("1/x - 1/(x+1)"), "1 / (x * (x + 1))");
+        assert_eq!(combined("x + 1/x"), "(x^2 + 1) / x");
+        assert_eq!(combined("a/b + c/b"), "(a + c) / b");
+        assert_eq!(combined("x + y"), "x + y");
+    }
+
+    #[test]
+    fn divide_cancels_common_factors() {
+        assert_eq!(simplified("(x+1)/(x+1)"), "1");
+        assert_eq!(simplified("sin(x)/sin(x)"), "1");
+        assert_eq!(simplified("(x*y)/y"), "x");
+        assert_eq!(simplified("(2*x*sin(x))/(x*sin(x))"), "2");
+        assert_eq!(simplified("x/(1/y)"), "x * y");
     }
 }
@@ -0,0 +1,55 @@
+use crate::expression::Expression;
+
+impl Expression {
+    // 把每个 Variable(var) 替换为 replacement 的副本，包括 Log 的底数
+    pub fn substitute(&self, var: &str, replacement: &Expression) -> Expression {
+        match self {
+            Expression::Variable(name) if name == var => replacement.clone(),
+            _ => self.map_children(|child| child.substitute(var, replacement)),
+        }
+    }
+
+    // 把变量固定为数值后化简，得到关于其余变量的特化表达式
+    pub fn substitute_constant(&self, var: &str, value: f64) -> Expression {
+        self.substitute(var, &Expression::constant(value)).simplify()
+    }
+
+    // 按结构相等（PartialEq）替换所有与 target 相同的子表达式，
+    // 匹配的节点整体替换，不再深入其内部
+    pub fn replace_subexpr(&self, target: &Expression, replacement: &Expression) -> Expression {
+        if self == target {
+            replacement.clone()
+        } else {
+            self.map_children(|child| child.replace_subexpr(target, replacement))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parse, Expression};
+
+    #[test]
+    fn substitute_replaces_every_occurrence() {
+        let y_plus_one = parse("y + 1").unwrap();
+        let result = parse("x^2 + x").unwrap().substitute("x", &y_plus_one);
+        let expected = Expression::add(
+            Expression::power(y_plus_one.clone(), Expression::constant(2.0)),
+            y_plus_one.clone(),
+        );
+        assert_eq!(result, expected);
+
+        let log = parse("log(x, x)").unwrap().substitute("x", &y_plus_one);
+        assert_eq!(log, Expression::log(y_plus_one.clone(), y_plus_one));
+        let t = parse("2*t + 1").unwrap();
+        assert_eq!(parse("sin(x) * z").unwrap().substitute("x", &t).to_string(), "sin(2 * t + 1) * z");
+    }
+
+    #[test]
+    fn substitute_constant_folds_the_specialized_expression() {
+        let specialized = parse("a*x + b").unwrap().substitute_constant("a", 2.0);
+        assert_eq!(specialized.to_string(), "2 * x + b");
+        assert_eq!(parse("a^2*x + a").unwrap().substitute_constant("a", 2.0).to_string(), "4 * x + 2");
+        assert_eq!(parse("sin(a) + a").unwrap().substitute_constant("a", 0.0), Expression::constant(0.0));
+    }
+}
@@ -0,0 +1,68 @@
+use crate::expression::{format_constant, Expression};
+
+impl Expression {
+    // 竖排的树形文本，每行一个节点，子节点用 ├─ 和 └─ 连到父节点下面，例如 2*x + 1：
+    //   Add
+    //   ├─ Multiply
+    //   │  ├─ Constant(2)
+    //   │  └─ Variable(x)
+    //   └─ Constant(1)
+    pub fn to_tree_string(&self) -> String {
+        let mut out = String::new();
+        self.write_tree_node(&mut out, "", "");
+        out
+    }
+
+    // branch 是当前行节点名之前的连线，indent 是子树各行共同的前缀
+    fn write_tree_node(&self, out: &mut String, branch: &str, indent: &str) {
+        out.push_str(branch);
+        out.push_str(&self.tree_label());
+        out.push('\n');
+
+        let children = self.children();
+        for (i, child) in children.iter().enumerate() {
+            let (branch, continuation) = if i + 1 == children.len() { ("└─ ", "   ") } else { ("├─ ", "│  ") };
+            child.write_tree_node(out, &format!("{}{}", indent, branch), &format!("{}{}", indent, continuation));
+        }
+    }
+
+    fn tree_label(&self) -> String {
+        let name = match self {
+            Expression::Constant(c) => return format!("Constant({})", format_constant(*c)),
+            Expression::Variable(name) => return format!("Variable({})", name),
+            Expression::Add(_, _) => "Add",
+            Expression::Subtract(_, _) => "Subtract",
+            Expression::Multiply(_, _) => "Multiply",
+            Expression::Divide(_, _) => "Divide",
+            Expression::Power(_, _) => "Power",
+            Expression::Root(_, _) => "Root",
+            Expression::Sqrt(_) => "Sqrt",
+            Expression::Negate(_) => "Negate",
+            Expression::Sin(_) => "Sin",
+            Expression::Cos(_) => "Cos",
+            Expression::Tan(_) => "Tan",
+            Expression::Sec(_) => "Sec",
+            Expression::Csc(_) => "Csc",
+            Expression::Cot(_) => "Cot",
+            Expression::Arcsin(_) => "Arcsin",
+            Expression::Arccos(_) => "Arccos",
+            Expression::Arctan(_) => "Arctan",
+            Expression::Exp(_) => "Exp",
+            Expression::Ln(_) => "Ln",
+            Expression::Log(_, _) => "Log",
+            Expression::Sinh(_) => "Sinh",
+            Expression::Cosh(_) => "Cosh",
+            Expression::Tanh(_) => "Tanh",
+            Expression::Arctanh(_) => "Arctanh",
+            Expression::Factorial(_) => "Factorial",
+            Expression::Polygamma(_, _) => "Polygamma",
+            Expression::Min(_, _) => "Min",
+            Expression::Max(_, _) => "Max",
+            Expression::Piecewise(_, _, _) => "Piecewise",
+            Expression::Abs(_) => "Abs",
+            Expression::Heaviside(_) => "Heaviside",
+            Expression::Clamp(_, _, _) => "Clamp",
+        };
+        name.to_string()
+    }
+}